@@ -1,47 +1,116 @@
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use chrono::Local;
+use chrono::{Local, NaiveDate};
+use image::imageops::{self, FilterType};
+use image::DynamicImage;
 
+use crate::config::DEFAULT_DATE_FORMAT;
 use crate::logger::Logger;
+use crate::postprocess::save_jpeg;
+use crate::{day_image, original_path};
+
+/// The date of a day folder named with `format`, or with the default or ISO
+/// format so folders from before a `date_format` change are still recognised.
+pub fn parse_day_name(name: &str, format: &str) -> Option<NaiveDate> {
+    [format, DEFAULT_DATE_FORMAT, "%Y-%m-%d"]
+        .into_iter()
+        .find_map(|f| NaiveDate::parse_from_str(name, f).ok())
+}
+
+/// Date-named folders directly inside `base_folder` (see `parse_day_name`), unsorted.
+pub fn dated_folders(base_folder: &Path, format: &str) -> Vec<(NaiveDate, PathBuf)> {
+    let entries = match fs::read_dir(base_folder) {
+        Ok(e) => e,
+        Err(_) => return Vec::new(),
+    };
+
+    entries
+        .flatten()
+        .filter(|e| e.file_type().is_ok_and(|ft| ft.is_dir()))
+        .filter_map(|e| {
+            let date = parse_day_name(&e.file_name().to_string_lossy(), format)?;
+            Some((date, e.path()))
+        })
+        .collect()
+}
+
+fn file_size(path: &Path) -> u64 {
+    fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+/// Shrink an archived folder's image to at most `max_width` pixels wide and
+/// drop its `_original.jpg`. Returns the bytes saved.
+fn shrink_archived(folder: &Path, max_width: u32, logger: &mut Logger) -> u64 {
+    let name = folder.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let image_path = day_image(folder, &name);
+    let original = original_path(&image_path);
+    let before = file_size(&image_path) + file_size(&original);
+
+    if original.exists() && fs::remove_file(&original).is_err() {
+        logger.log(&format!("Failed to remove {}", original.display()));
+    }
+
+    match image::open(&image_path) {
+        Ok(img) if img.width() > max_width => {
+            let (w, h) = (img.width(), img.height());
+            let new_h = ((h as u64 * max_width as u64) / w as u64).max(1) as u32;
+            let small = imageops::resize(&img.to_rgb8(), max_width, new_h, FilterType::Lanczos3);
+            // Write beside the image first so a failed encode keeps the full-size copy.
+            let tmp = image_path.with_extension("tmp");
+            let saved = save_jpeg(DynamicImage::ImageRgb8(small), &tmp)
+                .map_err(|e| e.to_string())
+                .and_then(|_| fs::rename(&tmp, &image_path).map_err(|e| e.to_string()));
+            match saved {
+                Ok(()) => logger.log(&format!("Downscaled archived {name}: {w}x{h} -> {max_width}x{new_h}")),
+                Err(e) => {
+                    let _ = fs::remove_file(&tmp);
+                    logger.log(&format!("Failed to downscale {}: {e}", image_path.display()));
+                }
+            }
+        }
+        Ok(_) => {}
+        Err(e) => logger.log(&format!("Failed to open {} for downscaling: {e}", image_path.display())),
+    }
+
+    before.saturating_sub(file_size(&image_path))
+}
 
 /// Move date-named folders older than `days` into a yearly archive structure.
+/// With `max_width > 0`, each moved folder's image is then downscaled to that
+/// width and its original removed.
 pub fn archive_old_folders(
     base_folder: &Path,
     archive_folder: &Path,
     logger: &mut Logger,
     days: u32,
+    max_width: u32,
+    date_format: &str,
 ) {
     let _ = fs::create_dir_all(archive_folder);
 
     let cutoff = Local::now().date_naive() - chrono::Duration::days(days as i64);
     let mut count = 0u32;
+    let mut saved = 0u64;
 
-    let entries = match fs::read_dir(base_folder) {
-        Ok(e) => e,
-        Err(_) => return,
-    };
-
-    for entry in entries.flatten() {
-        if !entry.file_type().is_ok_and(|ft| ft.is_dir()) {
-            continue;
-        }
+    for (date, path) in dated_folders(base_folder, date_format) {
+        if date < cutoff {
+            let year_folder = archive_folder.join(date.format("%Y").to_string());
+            let _ = fs::create_dir_all(&year_folder);
 
-        let name = entry.file_name();
-        let name_str = name.to_string_lossy();
-
-        // Only process folders matching the date pattern YYYY.MM.DD
-        if let Ok(date) = chrono::NaiveDate::parse_from_str(&name_str, "%Y.%m.%d") {
-            if date < cutoff {
-                let year_folder = archive_folder.join(date.format("%Y").to_string());
-                let _ = fs::create_dir_all(&year_folder);
-
-                if fs::rename(entry.path(), year_folder.join(&*name_str)).is_ok() {
-                    count += 1;
+            let Some(name) = path.file_name() else { continue };
+            let target = year_folder.join(name);
+            if fs::rename(&path, &target).is_ok() {
+                count += 1;
+                if max_width > 0 {
+                    saved += shrink_archived(&target, max_width, logger);
                 }
             }
         }
     }
 
     logger.log(&format!("Archived {count} folders"));
+    if saved > 0 {
+        logger.log(&format!("Archive downscaling saved {:.1} MB", saved as f64 / (1024.0 * 1024.0)));
+    }
 }
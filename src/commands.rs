@@ -0,0 +1,350 @@
+//! Command-line commands other than the default run.
+
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::archive::{dated_folders, parse_day_name};
+use crate::config::{read_date_format, Config};
+use crate::history::{load_history, HISTORY_FILE};
+use crate::logger::Logger;
+use crate::metadata::load_metadata;
+use crate::watermark::add_watermarks;
+use crate::{
+    data_folder, day_image, get_base_path, load_status, original_path, parse_api_response, today_name, verify_image,
+};
+
+#[cfg(windows)]
+#[link(name = "kernel32")]
+extern "system" {
+    fn AttachConsole(dwProcessId: u32) -> i32;
+}
+
+/// Attach to the console of the shell that started us, so `println!` and
+/// prompts reach it: the Windows build is a GUI-subsystem program and starts
+/// without one. Output redirected to a file or pipe works either way.
+#[cfg(windows)]
+pub fn attach_console() {
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+pub fn attach_console() {}
+
+/// Open `path` with the platform file manager / default application.
+/// Returns the process exit code.
+pub fn open_path(path: &Path) -> i32 {
+    if !path.exists() {
+        eprintln!("{} does not exist yet", path.display());
+        return 1;
+    }
+    let opener = if cfg!(target_os = "windows") {
+        "explorer.exe"
+    } else if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    // Don't wait: explorer.exe returns a non-zero code even on success.
+    match Command::new(opener).arg(path).spawn() {
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("Failed to open {} with {opener}: {e}", path.display());
+            1
+        }
+    }
+}
+
+/// `--status`: print today's state as JSON. Returns the process exit code
+/// (0 when today's run completed, 1 otherwise).
+pub fn print_status() -> i32 {
+    let name = today_name();
+    let dfolder = data_folder().join(&name);
+    let status = load_status(&dfolder.join("status.json"));
+    let image_path = day_image(&dfolder, &name);
+    let metadata = load_metadata(&dfolder.join("metadata.json"))
+        .or_else(|| parse_api_response(&dfolder.join("api.json"), ""));
+
+    let report = serde_json::json!({
+        "date": name,
+        "completed": status.completed,
+        "downloaded": status.downloaded,
+        "watermark_added": status.watermark_added,
+        "adjusted": status.adjusted,
+        "wallpaper_set": status.wallpaper_set,
+        "completed_time": status.completed_time,
+        "download_time": status.download_time,
+        "image_path": image_path.exists().then(|| image_path.display().to_string()),
+        "source_url": metadata.as_ref().map(|m| m.url.clone()),
+        "title": metadata.as_ref().map(|m| m.title.clone()),
+        "copyright": metadata.as_ref().map(|m| m.copyright.clone()),
+        "duplicate_of": metadata.as_ref().and_then(|m| m.duplicate_of.clone()),
+    });
+    match serde_json::to_string_pretty(&report) {
+        Ok(json) => println!("{json}"),
+        Err(e) => eprintln!("Failed to serialize status: {e}"),
+    }
+
+    if status.completed { 0 } else { 1 }
+}
+
+/// `--history`: print `history.json` from the data folder. Returns the
+/// process exit code (1 when there is no readable history).
+pub fn print_history() -> i32 {
+    let path = data_folder().join(HISTORY_FILE);
+    let Some(entries) = load_history(&path) else {
+        eprintln!("No readable {} yet; set \"history\": true in config.json to record runs", path.display());
+        return 1;
+    };
+    match serde_json::to_string_pretty(&entries) {
+        Ok(json) => {
+            println!("{json}");
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to serialize history: {e}");
+            1
+        }
+    }
+}
+
+/// Ask for confirmation on stdin. Anything other than `y`/`yes` declines, and
+/// so does a missing console (e.g. a scheduled task), with a hint to use `--yes`.
+fn confirm(prompt: &str) -> bool {
+    if !io::stdin().is_terminal() {
+        eprintln!("{prompt} Cannot ask without an interactive console; pass --yes to confirm");
+        return false;
+    }
+    print!("{prompt} [y/N] ");
+    let _ = io::stdout().flush();
+    let mut line = String::new();
+    io::stdin().read_line(&mut line).is_ok()
+        && matches!(line.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
+
+/// Delete a file or directory, refusing anything not strictly inside `root`.
+fn remove_within(root: &Path, path: &Path) -> bool {
+    let (root, target) = match (fs::canonicalize(root), fs::canonicalize(path)) {
+        (Ok(r), Ok(t)) => (r, t),
+        _ => return false,
+    };
+    if target == root || !target.starts_with(&root) {
+        eprintln!("Refusing to delete {} outside {}", target.display(), root.display());
+        return false;
+    }
+
+    let result = if target.is_dir() {
+        fs::remove_dir_all(&target)
+    } else {
+        fs::remove_file(&target)
+    };
+    match result {
+        Ok(_) => {
+            println!("Removed {}", path.display());
+            true
+        }
+        Err(e) => {
+            eprintln!("Failed to remove {}: {e}", path.display());
+            false
+        }
+    }
+}
+
+/// Files the program itself keeps in the data folder root.
+const DATA_FILES: &[&str] = &["run.lock", "local_state.json", HISTORY_FILE, "history.json.bak", "history.tmp"];
+
+/// `--clean` purges today's folder; `--clean-all` purges every day folder and
+/// data file except `Archive`. Anything else in the data folder is left alone,
+/// since `data_dir` may be `.` or any folder shared with other files.
+/// Returns the process exit code.
+pub fn clean(all: bool, assume_yes: bool) -> i32 {
+    let root = data_folder();
+    if !root.is_absolute() || !root.is_dir() {
+        eprintln!("Data folder {} not found", root.display());
+        return 1;
+    }
+
+    let (targets, scope): (Vec<PathBuf>, String) = if all {
+        let date_format = read_date_format(&get_base_path().join("config.json"));
+        let entries: Vec<PathBuf> = fs::read_dir(&root)
+            .map(|rd| {
+                rd.flatten()
+                    .filter(|e| {
+                        let name = e.file_name().to_string_lossy().into_owned();
+                        let is_dir = e.file_type().is_ok_and(|ft| ft.is_dir());
+                        if is_dir {
+                            parse_day_name(&name, &date_format).is_some()
+                        } else {
+                            DATA_FILES.contains(&name.as_str())
+                        }
+                    })
+                    .map(|e| e.path())
+                    .collect()
+            })
+            .unwrap_or_default();
+        if entries.is_empty() {
+            eprintln!(
+                "Refusing to clean {}: it holds no day folders or AutoWallpaper data files",
+                root.display()
+            );
+            return 1;
+        }
+        (entries, format!("all day folders and data files in {} (Archive is kept)", root.display()))
+    } else {
+        let dfolder = root.join(today_name());
+        // List files individually so each removal is reported, then the folder itself.
+        let mut entries: Vec<PathBuf> = fs::read_dir(&dfolder)
+            .map(|rd| rd.flatten().map(|e| e.path()).collect())
+            .unwrap_or_default();
+        if dfolder.exists() {
+            entries.push(dfolder.clone());
+        }
+        (entries, dfolder.display().to_string())
+    };
+
+    if targets.is_empty() {
+        println!("Nothing to clean");
+        return 0;
+    }
+    if !assume_yes && !confirm(&format!("Delete {scope}?")) {
+        println!("Aborted");
+        return 1;
+    }
+
+    let failed = targets.iter().filter(|t| !remove_within(&root, t)).count();
+    if failed == 0 { 0 } else { 1 }
+}
+
+/// Whether any image file directly inside `folder` decodes.
+fn has_valid_image(folder: &Path) -> bool {
+    let mut quiet = Logger::silent();
+    fs::read_dir(folder)
+        .map(|rd| {
+            rd.flatten().any(|e| {
+                let path = e.path();
+                let is_image = path
+                    .extension()
+                    .and_then(|x| x.to_str())
+                    .is_some_and(|x| matches!(x.to_ascii_lowercase().as_str(), "jpg" | "jpeg" | "png"));
+                is_image && verify_image(&path, (0, 0), &mut quiet)
+            })
+        })
+        .unwrap_or(false)
+}
+
+/// `--doctor`: report day folders without a valid image and leftover `.tmp`
+/// files from interrupted downloads. With `--fix`, removes the `.tmp` files and
+/// deletes past folders that hold no valid image; today's folder is kept so the
+/// next run downloads it again. Returns the process exit code (0 when nothing
+/// is left to fix).
+pub fn doctor(fix: bool) -> i32 {
+    let root = data_folder();
+    if !root.is_dir() {
+        eprintln!("Data folder {} not found", root.display());
+        return 1;
+    }
+    let today = today_name();
+
+    let mut folders = dated_folders(&root, &read_date_format(&get_base_path().join("config.json")));
+    folders.sort_by_key(|(date, _)| *date);
+
+    let (mut found, mut unresolved) = (0, 0);
+    for (_, folder) in &folders {
+        let name = folder.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let image = day_image(folder, &name);
+        let image_ok = verify_image(&image, (0, 0), &mut Logger::silent());
+        let tmp_files: Vec<PathBuf> = fs::read_dir(folder)
+            .map(|rd| {
+                rd.flatten()
+                    .map(|e| e.path())
+                    .filter(|p| p.extension().is_some_and(|x| x.eq_ignore_ascii_case("tmp")))
+                    .collect()
+            })
+            .unwrap_or_default();
+        if image_ok && tmp_files.is_empty() {
+            continue;
+        }
+
+        let mut problems = Vec::new();
+        if !image_ok {
+            problems.push("no valid image".to_string());
+        }
+        if !tmp_files.is_empty() {
+            problems.push(format!("{} leftover .tmp file(s)", tmp_files.len()));
+        }
+        println!("{name}: {}", problems.join(", "));
+        found += 1;
+        if !fix {
+            unresolved += 1;
+            continue;
+        }
+
+        let mut resolved = tmp_files.iter().filter(|t| !remove_within(&root, t)).count() == 0;
+        if !image_ok {
+            if name == today {
+                println!("{name}: will be downloaded again on the next run");
+            } else if has_valid_image(folder) {
+                // Only the main image is broken; never delete a folder holding a usable picture.
+                println!("{name}: kept, folder still contains a valid image");
+                resolved = false;
+            } else {
+                resolved &= remove_within(&root, folder);
+            }
+        }
+        if !resolved {
+            unresolved += 1;
+        }
+    }
+
+    if found == 0 {
+        println!("Checked {} folders, nothing to fix", folders.len());
+        0
+    } else if unresolved == 0 {
+        println!("Checked {} folders, fixed {found}", folders.len());
+        0
+    } else {
+        println!(
+            "Checked {} folders, {unresolved} need attention{}",
+            folders.len(),
+            if fix { "" } else { " (run with --fix to repair)" }
+        );
+        1
+    }
+}
+
+/// `--preview [image]`: render the configured watermarks onto `input` (by
+/// default today's unprocessed image) as `preview.jpg` in the current
+/// directory, regardless of `wtm`/`watermark_days`. Today's files, status and
+/// the wallpaper are left alone. Returns the process exit code.
+pub fn preview(input: Option<&Path>, config: &Config, open: bool, logger: &mut Logger) -> i32 {
+    let input = match input {
+        Some(path) => path.to_path_buf(),
+        None => {
+            let name = today_name();
+            let image = day_image(&data_folder().join(&name), &name);
+            let original = original_path(&image);
+            if original.exists() { original } else { image }
+        }
+    };
+    if !input.is_file() {
+        eprintln!("{} not found", input.display());
+        return 1;
+    }
+
+    let output = PathBuf::from("preview.jpg");
+    // A stale preview must not pass the check below if rendering fails.
+    let _ = fs::remove_file(&output);
+    logger.log(&format!("Preview: watermarking {} into {}", input.display(), output.display()));
+    add_watermarks(&input, &output, config, &get_base_path(), logger);
+    if !verify_image(&output, (0, 0), logger) {
+        eprintln!("Failed to render {}, see the log", output.display());
+        return 1;
+    }
+    println!("Preview written to {}", output.display());
+
+    if open { open_path(&output) } else { 0 }
+}
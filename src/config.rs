@@ -1,18 +1,44 @@
+use std::env;
 use std::fs;
+use std::io;
 use std::path::Path;
 
+use chrono::format::{Item, StrftimeItems};
+use chrono::{NaiveDate, Weekday};
 use serde::Serialize;
 use serde_json::Value;
 
+use crate::display::{layout_signature, monitor_sizes, parse_layout_signature};
 use crate::logger::Logger;
 
 // ── Constants ────────────────────────────────────────────────────────────────
 
 pub const ARCHIVE_DAYS: u32 = 10;
+pub const DEFAULT_FILENAME_PATTERN: &str = "{date}.jpg";
+pub const DEFAULT_DATE_FORMAT: &str = "%Y.%m.%d";
 pub const IMAGE_QUALITY: u8 = 98;
+/// Timestamped backups of an unparseable config kept beside it.
+const CONFIG_BACKUPS: usize = 5;
 
 // ── Watermark ────────────────────────────────────────────────────────────────
 
+/// Drop shadow drawn beneath a text watermark.
+#[derive(Debug, Clone, Serialize)]
+pub struct TextShadow {
+    pub shadow_color: [u8; 4],
+    pub shadow_offset_x: f32,
+    pub shadow_offset_y: f32,
+    /// Gaussian blur sigma in pixels; `0` draws a hard shadow.
+    pub shadow_blur: f32,
+}
+
+/// Vertical gradient fill for a text watermark, top to bottom of the whole block.
+#[derive(Debug, Clone, Serialize)]
+pub struct TextGradient {
+    pub gradient_start: [u8; 4],
+    pub gradient_end: [u8; 4],
+}
+
 /// Watermark definition: either an image overlay or rendered text.
 #[derive(Debug, Clone, Serialize)]
 #[serde(tag = "type")]
@@ -26,6 +52,16 @@ pub enum Watermark {
         #[serde(rename = "posY")]
         pos_y: f64,
         opacity: u8,
+        /// Frame to use from an animated GIF/APNG/WebP.
+        frame_index: u32,
+        /// Draw order; lower values are drawn first (underneath).
+        z: i32,
+        /// Recolour every pixel of the logo to this RGB, keeping its alpha.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        tint_color: Option<[u8; 3]>,
+        /// Mirror the logo left-right / upside-down.
+        flip_h: bool,
+        flip_v: bool,
     },
     #[serde(rename = "text")]
     Text {
@@ -40,6 +76,13 @@ pub enum Watermark {
         font_size: u32,
         font_color: [u8; 4],
         font_weight: String,
+        letter_spacing: f32,
+        line_height: f32,
+        z: i32,
+        #[serde(flatten, skip_serializing_if = "Option::is_none")]
+        shadow: Option<TextShadow>,
+        #[serde(flatten, skip_serializing_if = "Option::is_none")]
+        gradient: Option<TextGradient>,
     },
 }
 
@@ -50,6 +93,11 @@ impl Watermark {
             pos_x: 2.0,
             pos_y: 1.2,
             opacity: 50,
+            frame_index: 0,
+            z: 0,
+            tint_color: None,
+            flip_h: false,
+            flip_v: false,
         }
     }
 
@@ -63,13 +111,24 @@ impl Watermark {
             font_size: 46,
             font_color: [128, 128, 128, 192],
             font_weight: "normal".into(),
+            letter_spacing: 0.0,
+            line_height: 1.0,
+            z: 0,
+            shadow: None,
+            gradient: None,
+        }
+    }
+
+    pub fn z(&self) -> i32 {
+        match self {
+            Self::Image { z, .. } | Self::Text { z, .. } => *z,
         }
     }
 
     /// One-line summary for log output.
     pub fn summary(&self) -> String {
         match self {
-            Self::Image { path, pos_x, pos_y, opacity } => {
+            Self::Image { path, pos_x, pos_y, opacity, .. } => {
                 format!("type=image, path={path}, posX={pos_x}, posY={pos_y}, opacity={opacity}")
             }
             Self::Text { content, pos_x, pos_y, opacity, .. } => {
@@ -79,40 +138,443 @@ impl Watermark {
     }
 }
 
+// ── Copyright watermark ──────────────────────────────────────────────────────
+
+/// Built-in copyright stamp drawn before user watermarks.
+#[derive(Debug, Clone, Serialize)]
+pub struct CopyrightWatermark {
+    pub enabled: bool,
+    pub text: String,
+    pub font: String,
+    pub size: u32,
+    pub color: [u8; 4],
+    #[serde(rename = "posX")]
+    pub pos_x: f64,
+    #[serde(rename = "posY")]
+    pub pos_y: f64,
+    /// Draw order relative to `watermarks`; ties keep the copyright first.
+    pub z: i32,
+}
+
+/// English stamp text; also the fallback for markets without a translation.
+const DEFAULT_COPYRIGHT_TEXT: &str = "   Auto Change Wallpaper By LtqX\n\nPictures all from and belong to Bing";
+const DEFAULT_COPYRIGHT_FONT: &str = "BRADHITC.TTF";
+
+/// Localized stamp text as `(market or language prefix, text, font)`. Exact
+/// markets are listed before their language prefix. The font replaces the
+/// default (Latin-only) font for scripts it can't render.
+const COPYRIGHT_TRANSLATIONS: &[(&str, &str, Option<&str>)] = &[
+    ("zh-TW", "   Auto Change Wallpaper By LtqX\n\n圖片均來自必應，版權歸必應所有", Some("msjh.ttc")),
+    ("zh-HK", "   Auto Change Wallpaper By LtqX\n\n圖片均來自必應，版權歸必應所有", Some("msjh.ttc")),
+    ("zh", "   Auto Change Wallpaper By LtqX\n\n图片均来自必应，版权归必应所有", Some("msyh.ttc")),
+    ("ja", "   Auto Change Wallpaper By LtqX\n\n画像はすべて Bing から提供され、Bing に帰属します", Some("YuGothM.ttc")),
+    ("ko", "   Auto Change Wallpaper By LtqX\n\n모든 사진의 출처와 저작권은 Bing에 있습니다", Some("malgun.ttf")),
+    ("de", "   Auto Change Wallpaper By LtqX\n\nAlle Bilder stammen von Bing und gehören Bing", None),
+    ("fr", "   Auto Change Wallpaper By LtqX\n\nToutes les images proviennent de Bing et lui appartiennent", None),
+    ("es", "   Auto Change Wallpaper By LtqX\n\nTodas las imágenes provienen de Bing y le pertenecen", None),
+];
+
+impl Default for CopyrightWatermark {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            text: DEFAULT_COPYRIGHT_TEXT.into(),
+            font: DEFAULT_COPYRIGHT_FONT.into(),
+            size: 62,
+            color: [128, 128, 128, 204],
+            pos_x: 2.0,
+            pos_y: 1.2,
+            z: 0,
+        }
+    }
+}
+
+impl CopyrightWatermark {
+    /// The stamp translated for `mkt`, with the locale used. Customised text is
+    /// left alone (`None`); unknown markets get the English default.
+    pub fn localized(&self, mkt: &str) -> Option<(&'static str, CopyrightWatermark)> {
+        if self.text != DEFAULT_COPYRIGHT_TEXT {
+            return None;
+        }
+        let lang = mkt.split('-').next().unwrap_or_default();
+        let found = COPYRIGHT_TRANSLATIONS
+            .iter()
+            .find(|(key, ..)| key.eq_ignore_ascii_case(mkt))
+            .or_else(|| COPYRIGHT_TRANSLATIONS.iter().find(|(key, ..)| key.eq_ignore_ascii_case(lang)));
+        let Some(&(locale, text, font)) = found else {
+            return Some(("en", self.clone()));
+        };
+        let mut localized = self.clone();
+        localized.text = text.into();
+        if let Some(font) = font.filter(|_| self.font == DEFAULT_COPYRIGHT_FONT) {
+            localized.font = font.into();
+        }
+        Some((locale, localized))
+    }
+
+    /// Express the stamp as a bold text watermark so it shares the `Canvas` path.
+    pub fn to_watermark(&self) -> Watermark {
+        Watermark::Text {
+            content: self.text.clone(),
+            pos_x: self.pos_x,
+            pos_y: self.pos_y,
+            opacity: 100,
+            font_type: self.font.clone(),
+            font_size: self.size,
+            font_color: self.color,
+            font_weight: "bold".into(),
+            letter_spacing: 0.0,
+            line_height: 1.0,
+            z: self.z,
+            shadow: None,
+            gradient: None,
+        }
+    }
+}
+
+// ── Adjustments ──────────────────────────────────────────────────────────────
+
+/// Colour adjustments applied after watermarking. Defaults are no-ops.
+#[derive(Debug, Clone, Serialize)]
+pub struct Adjustments {
+    /// Added to every channel (`imageops::brighten`).
+    pub brightness: i32,
+    /// Percent contrast change (`imageops::contrast`).
+    pub contrast: f32,
+    /// HSL saturation multiplier.
+    pub saturation: f32,
+    pub grayscale: bool,
+}
+
+impl Default for Adjustments {
+    fn default() -> Self {
+        Self { brightness: 0, contrast: 0.0, saturation: 1.0, grayscale: false }
+    }
+}
+
+impl Adjustments {
+    pub fn is_noop(&self) -> bool {
+        self.brightness == 0 && self.contrast == 0.0 && self.saturation == 1.0 && !self.grayscale
+    }
+}
+
+// ── Caption bar ──────────────────────────────────────────────────────────────
+
+/// Solid strip added below the image with its title and copyright. The canvas
+/// grows by `height`, so the photo itself is never covered.
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptionBar {
+    pub enabled: bool,
+    /// Strip height in pixels.
+    pub height: u32,
+    pub background: [u8; 4],
+    pub text_color: [u8; 4],
+    pub font: String,
+    pub font_size: u32,
+}
+
+impl Default for CaptionBar {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            height: 80,
+            background: [0, 0, 0, 255],
+            text_color: [255, 255, 255, 255],
+            font: "arial.ttf".into(),
+            font_size: 32,
+        }
+    }
+}
+
+// ── Safe area ────────────────────────────────────────────────────────────────
+
+/// Distance of one safe-area edge from the image border. Serialized as a number
+/// of pixels or a `"5%"` string.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Inset {
+    Px(u32),
+    /// Percentage of the image width (left/right) or height (top/bottom).
+    Percent(f64),
+}
+
+impl Default for Inset {
+    fn default() -> Self {
+        Inset::Px(0)
+    }
+}
+
+impl Inset {
+    /// Resolve against the image side length the edge is measured along.
+    pub fn to_px(self, side: u32) -> f32 {
+        match self {
+            Inset::Px(px) => px as f32,
+            Inset::Percent(p) => (side as f64 * p / 100.0) as f32,
+        }
+    }
+}
+
+impl std::fmt::Display for Inset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Inset::Px(px) => write!(f, "{px}"),
+            Inset::Percent(p) => write!(f, "{p}%"),
+        }
+    }
+}
+
+impl Serialize for Inset {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Inset::Px(px) => serializer.serialize_u32(*px),
+            Inset::Percent(_) => serializer.collect_str(self),
+        }
+    }
+}
+
+/// Margins watermarks are kept out of, e.g. where the taskbar covers the
+/// wallpaper. All zero by default, which leaves placement unchanged.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct SafeArea {
+    pub top: Inset,
+    pub right: Inset,
+    pub bottom: Inset,
+    pub left: Inset,
+}
+
+impl SafeArea {
+    pub fn is_noop(&self) -> bool {
+        [self.top, self.right, self.bottom, self.left].iter().all(|i| i.to_px(1) == 0.0)
+    }
+}
+
+impl std::fmt::Display for SafeArea {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "top={}, right={}, bottom={}, left={}", self.top, self.right, self.bottom, self.left)
+    }
+}
+
+/// A pixel count, or a percentage string below 50 so opposite edges can't meet.
+fn parse_inset(v: &Value) -> Option<Inset> {
+    if let Some(n) = v.as_u64() {
+        return u32::try_from(n).ok().map(Inset::Px);
+    }
+    let s = v.as_str()?.trim();
+    match s.strip_suffix('%') {
+        Some(p) => p.trim().parse::<f64>().ok().filter(|p| (0.0..50.0).contains(p)).map(Inset::Percent),
+        None => s.parse::<u32>().ok().map(Inset::Px),
+    }
+}
+
+/// Edges that are missing keep `0`; `None` when the value isn't an object or
+/// an edge is invalid.
+fn parse_safe_area(v: &Value) -> Option<SafeArea> {
+    let obj = v.as_object()?;
+    let edge = |key: &str| obj.get(key).map_or(Some(Inset::default()), parse_inset);
+    Some(SafeArea { top: edge("top")?, right: edge("right")?, bottom: edge("bottom")?, left: edge("left")? })
+}
+
+// ── Copy targets ─────────────────────────────────────────────────────────────
+
+/// One `copy_to_paths` destination. A bare string in the config means a
+/// `processed` copy to that path.
+#[derive(Debug, Clone, Serialize)]
+pub struct CopyTarget {
+    pub path: String,
+    /// `processed` (the wallpaper as set) or `original` (the clean download).
+    pub variant: String,
+}
+
+fn parse_copy_target(v: &Value) -> Option<CopyTarget> {
+    if let Some(path) = v.as_str() {
+        return Some(CopyTarget { path: path.into(), variant: "processed".into() });
+    }
+    let obj = v.as_object()?;
+    let path = obj.get("path")?.as_str()?.to_string();
+    let variant = match obj.get("variant").and_then(|v| v.as_str()) {
+        Some("original") => "original",
+        _ => "processed",
+    };
+    Some(CopyTarget { path, variant: variant.into() })
+}
+
 // ── Config ───────────────────────────────────────────────────────────────────
 
 /// Application configuration, validated and ready to use.
 #[derive(Debug, Clone, Serialize)]
 pub struct Config {
+    /// Data folder override; empty uses `%APPDATA%\AutoWallpaper`, `portable`
+    /// a `data` folder next to the exe. `AW_DATA_DIR` takes precedence.
+    pub data_dir: String,
+    /// Image source: `bing`, `himawari`, `command` or `local`.
+    pub source: String,
+    /// Shell command for the `command` source; prints an image URL or JSON.
+    pub source_command: String,
+    /// Folder of images for the `local` source.
+    pub local_folder: String,
+    /// `local` selection policy: `random`, `sequential` or `by-date`.
+    pub local_policy: String,
+    /// Himawari tiles per side (1, 2, 4, 8, 16 or 20); each tile is 550 px.
+    pub himawari_level: u32,
     pub idx: u8,
     pub mkt: String,
+    /// Markets tried in order when `mkt` yields no usable image.
+    pub mkt_fallbacks: Vec<String>,
+    /// When Bing hasn't published today's image yet, wait a little and then
+    /// accept the previous day's instead of just warning.
+    pub fallback_to_previous: bool,
     pub chk: bool,
+    /// Exit early if the last successful run was less than this many hours ago; `0` disables.
+    pub min_interval_hours: u32,
+    /// Set a random valid image from the last N dated folders instead of
+    /// today's; `0`/`1` always uses today's. At most `ARCHIVE_DAYS`.
+    pub pick_from_recent_days: u32,
+    /// Width archived images are downscaled to, dropping their originals; `0` keeps them as-is.
+    pub archive_max_width: u32,
+    /// Free space in MB required on the data volume before downloading; `0` disables the check.
+    pub min_free_mb: u32,
     pub ctd: bool,
+    /// Also use the image as the lock screen (Windows, needs admin rights).
+    pub set_lock_screen: bool,
+    /// How the wallpaper switches: `none` or `fade` (crossfade from the previous one).
+    pub transition: String,
     pub wtm: bool,
+    /// Weekdays (`mon`..`sun`) on which `wtm` applies; empty means every day.
+    pub watermark_days: Vec<String>,
     pub retry_delay: u32,
     pub retry_count: u32,
+    /// Longest server `Retry-After` hint honoured, in seconds (at least 60).
+    pub max_retry_after: u32,
+    pub max_bytes_per_sec: u32,
+    /// Seconds allowed to establish a connection.
+    pub connect_timeout_secs: u32,
+    /// Seconds allowed between received bytes.
+    pub read_timeout_secs: u32,
+    /// Upper bound in seconds on all downloads of a run, retries included; `0` means unbounded.
+    pub total_timeout_secs: u32,
+    /// Address family for downloads: `auto`, `v4` or `v6`.
+    pub ip_version: String,
+    pub min_width: u32,
+    pub min_height: u32,
+    pub gamma_correct: bool,
+    /// Centre-crop target as `width:height` (e.g. `21:9`); empty disables cropping.
+    pub aspect_crop: String,
+    pub copyright_watermark: CopyrightWatermark,
     pub watermarks: Vec<Watermark>,
+    /// Edge margins watermarks are moved out of.
+    pub safe_area: SafeArea,
+    pub adjustments: Adjustments,
+    pub caption_bar: CaptionBar,
+    /// chrono format for day folder, log and `{date}` names.
+    pub date_format: String,
+    /// Name of the day's image with `{date}`, `{market}`, `{idx}` and
+    /// `{title-slug}` tokens; `.jpg` is appended when missing.
+    pub filename_pattern: String,
     pub post_execution_apps: Vec<String>,
-    pub copy_to_paths: Vec<String>,
+    pub copy_to_paths: Vec<CopyTarget>,
+    /// Append a record of every run to `history.json` in the data folder.
+    pub history: bool,
+    /// GitHub "latest release" API URL checked by `--self-update`.
+    pub update_url: String,
+    /// Partial configs keyed by monitor layout (`1920x1080+2560x1440`); the
+    /// one matching the current layout overrides the keys above.
+    pub profiles: serde_json::Map<String, Value>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            data_dir: String::new(),
+            source: "bing".into(),
+            source_command: String::new(),
+            local_folder: String::new(),
+            local_policy: "random".into(),
+            himawari_level: 4,
             idx: 0,
             mkt: "zh-CN".into(),
+            mkt_fallbacks: vec![],
+            fallback_to_previous: false,
             chk: true,
+            min_interval_hours: 0,
+            pick_from_recent_days: 0,
+            archive_max_width: 0,
+            min_free_mb: 100,
             ctd: true,
+            set_lock_screen: false,
+            transition: "none".into(),
             wtm: false,
+            watermark_days: vec![],
             retry_delay: 3,
             retry_count: 10,
+            max_retry_after: 60,
+            max_bytes_per_sec: 0,
+            connect_timeout_secs: 30,
+            read_timeout_secs: 30,
+            total_timeout_secs: 0,
+            ip_version: "auto".into(),
+            min_width: 1920,
+            min_height: 1080,
+            gamma_correct: false,
+            aspect_crop: String::new(),
+            copyright_watermark: CopyrightWatermark::default(),
             watermarks: vec![Watermark::default_image(), Watermark::default_text()],
+            safe_area: SafeArea::default(),
+            adjustments: Adjustments::default(),
+            caption_bar: CaptionBar::default(),
+            date_format: DEFAULT_DATE_FORMAT.into(),
+            filename_pattern: DEFAULT_FILENAME_PATTERN.into(),
             post_execution_apps: vec![],
             copy_to_paths: vec![],
+            history: false,
+            update_url: String::new(),
+            profiles: serde_json::Map::new(),
         }
     }
 }
 
+impl Config {
+    /// `mkt` followed by its fallbacks, without duplicates.
+    pub fn markets(&self) -> Vec<&str> {
+        let mut markets: Vec<&str> = vec![&self.mkt];
+        for m in &self.mkt_fallbacks {
+            if !markets.contains(&m.as_str()) {
+                markets.push(m);
+            }
+        }
+        markets
+    }
+
+    /// Whether `watermark_days` allows watermarking on `day`.
+    pub fn watermark_active_on(&self, day: Weekday) -> bool {
+        self.watermark_days.is_empty()
+            || self.watermark_days.iter().any(|d| d.parse::<Weekday>().is_ok_and(|w| w == day))
+    }
+
+    /// Stable fingerprint of every setting that affects the processed image,
+    /// stored in `status.json` so same-day config edits are detected.
+    pub fn output_hash(&self) -> String {
+        let json = serde_json::json!({
+            "wtm": self.wtm,
+            "watermark_days": self.watermark_days,
+            "gamma_correct": self.gamma_correct,
+            "copyright_watermark": self.copyright_watermark,
+            "watermarks": self.watermarks,
+            "safe_area": self.safe_area,
+            "adjustments": self.adjustments,
+            "caption_bar": self.caption_bar,
+            "aspect_crop": self.aspect_crop,
+        })
+        .to_string();
+        format!("{:016x}", fnv1a64(json.as_bytes()))
+    }
+}
+
+/// 64-bit FNV-1a; unlike `DefaultHasher` its output is stable across Rust releases.
+fn fnv1a64(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
 // ── Flexible JSON value parsers ──────────────────────────────────────────────
 
 fn parse_u8(v: &Value, min: u8, max: u8, default: u8) -> u8 {
@@ -126,6 +588,32 @@ fn parse_u8(v: &Value, min: u8, max: u8, default: u8) -> u8 {
         .unwrap_or(default)
 }
 
+/// Named day offsets accepted for `idx`: how many days before today.
+fn parse_idx_name(s: &str) -> Option<u8> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "today" => Some(0),
+        "yesterday" => Some(1),
+        _ => None,
+    }
+}
+
+const WEEKDAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// Expand one `watermark_days` entry: a day name (`mon`, `Monday`) or an
+/// inclusive range such as `mon-fri` or `fri-mon` (wrapping).
+fn parse_weekday_spec(spec: &str) -> Option<Vec<&'static str>> {
+    let index = |s: &str| s.trim().parse::<Weekday>().ok().map(|w| w.num_days_from_monday() as usize);
+    let (start, end) = match spec.split_once('-') {
+        Some((a, b)) => (index(a)?, index(b)?),
+        None => {
+            let i = index(spec)?;
+            (i, i)
+        }
+    };
+    let len = (end + 7 - start) % 7 + 1;
+    Some((0..len).map(|k| WEEKDAY_NAMES[(start + k) % 7]).collect())
+}
+
 fn parse_u32_min(v: &Value, min: u32, default: u32) -> u32 {
     v.as_u64()
         .map(|n| (n.min(u32::MAX as u64) as u32).max(min))
@@ -144,36 +632,189 @@ fn parse_bool(v: &Value, default: bool) -> bool {
     }).unwrap_or(default)
 }
 
+fn parse_color(v: &Value) -> Option<[u8; 4]> {
+    let arr = v.as_array().filter(|arr| arr.len() == 4)?;
+    let v: Vec<u8> = arr
+        .iter()
+        .filter_map(|c| c.as_u64().map(|n| n.min(255) as u8))
+        .collect();
+    if v.len() == 4 { Some([v[0], v[1], v[2], v[3]]) } else { None }
+}
+
+/// Tokens accepted in `filename_pattern`.
+const FILENAME_TOKENS: [&str; 4] = ["{date}", "{market}", "{idx}", "{title-slug}"];
+
+/// Validate `filename_pattern`: known tokens only, and what's left must be
+/// legal in a Windows file name (no separators or reserved characters, no
+/// trailing dot or space, not a reserved device name).
+fn parse_filename_pattern(v: &Value) -> Option<String> {
+    let pattern = v.as_str()?.trim();
+    let mut literal = pattern.to_string();
+    for token in FILENAME_TOKENS {
+        literal = literal.replace(token, "x");
+    }
+    let legal = !pattern.is_empty()
+        && !literal.chars().any(|c| c.is_control() || r#"<>:"/\|?*{}"#.contains(c))
+        && !literal.ends_with(['.', ' ']);
+    let stem = literal.split('.').next().unwrap_or_default().to_ascii_uppercase();
+    let reserved = matches!(stem.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+        || ((stem.starts_with("COM") || stem.starts_with("LPT")) && stem.len() == 4 && stem.as_bytes()[3].is_ascii_digit());
+    (legal && !reserved).then(|| pattern.to_string())
+}
+
+/// Whether `format` turns a date into a non-empty, file-system-safe name that
+/// parses back to the same date, so day folders can still be found and archived.
+pub fn valid_date_format(format: &str) -> bool {
+    use std::fmt::Write;
+
+    let sample = NaiveDate::from_ymd_opt(2026, 11, 23).unwrap_or_default();
+    let mut name = String::new();
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error))
+        || write!(name, "{}", sample.format(format)).is_err()
+    {
+        return false;
+    }
+    !name.trim().is_empty()
+        && !name.chars().any(|c| c.is_control() || r#"<>:"/\|?*"#.contains(c))
+        && !name.ends_with(['.', ' '])
+        && NaiveDate::parse_from_str(&name, format).ok() == Some(sample)
+}
+
+fn parse_rgb(v: &Value) -> Option<[u8; 3]> {
+    let arr = v.as_array().filter(|arr| arr.len() == 3)?;
+    let v: Vec<u8> = arr
+        .iter()
+        .filter_map(|c| c.as_u64().map(|n| n.min(255) as u8))
+        .collect();
+    if v.len() == 3 { Some([v[0], v[1], v[2]]) } else { None }
+}
+
+fn parse_copyright_watermark(
+    v: &Value,
+    default: &CopyrightWatermark,
+    logger: &mut Logger,
+) -> Option<CopyrightWatermark> {
+    let obj = v.as_object()?;
+    let label = "Copyright watermark";
+    Some(CopyrightWatermark {
+        enabled: obj.get("enabled").map(|v| parse_bool(v, default.enabled)).unwrap_or(default.enabled),
+        text: obj.get("text").and_then(|v| v.as_str()).unwrap_or(&default.text).into(),
+        font: obj.get("font").and_then(|v| v.as_str()).filter(|s| !s.is_empty()).unwrap_or(&default.font).into(),
+        size: obj.get("size").and_then(|v| v.as_u64()).map(|n| (n as u32).max(1)).unwrap_or(default.size),
+        color: obj.get("color").and_then(parse_color).unwrap_or(default.color),
+        pos_x: parse_pos(obj, "posX", default.pos_x, label, logger),
+        pos_y: parse_pos(obj, "posY", default.pos_y, label, logger),
+        z: parse_z(obj),
+    })
+}
+
+fn parse_caption_bar(v: &Value) -> Option<CaptionBar> {
+    let obj = v.as_object()?;
+    let default = CaptionBar::default();
+    Some(CaptionBar {
+        enabled: obj.get("enabled").map(|v| parse_bool(v, default.enabled)).unwrap_or(default.enabled),
+        height: obj.get("height").map(|v| parse_u32_min(v, 1, default.height)).unwrap_or(default.height),
+        background: obj.get("background").and_then(parse_color).unwrap_or(default.background),
+        text_color: obj.get("text_color").and_then(parse_color).unwrap_or(default.text_color),
+        font: obj.get("font").and_then(|v| v.as_str()).filter(|s| !s.is_empty()).unwrap_or(&default.font).into(),
+        font_size: obj.get("font_size").map(|v| parse_u32_min(v, 1, default.font_size)).unwrap_or(default.font_size),
+    })
+}
+
+fn parse_adjustments(v: &Value) -> Option<Adjustments> {
+    let obj = v.as_object()?;
+    let default = Adjustments::default();
+    Some(Adjustments {
+        brightness: obj.get("brightness").and_then(|v| v.as_i64()).map(|n| n.clamp(-255, 255) as i32).unwrap_or(default.brightness),
+        contrast: obj.get("contrast").and_then(|v| v.as_f64()).map(|n| n as f32).unwrap_or(default.contrast),
+        saturation: obj.get("saturation").and_then(|v| v.as_f64()).filter(|&n| n >= 0.0).map(|n| n as f32).unwrap_or(default.saturation),
+        grayscale: obj.get("grayscale").map(|v| parse_bool(v, default.grayscale)).unwrap_or(default.grayscale),
+    })
+}
+
+fn parse_z(obj: &serde_json::Map<String, Value>) -> i32 {
+    obj.get("z")
+        .and_then(|v| v.as_i64())
+        .map(|n| n.clamp(i32::MIN as i64, i32::MAX as i64) as i32)
+        .unwrap_or(0)
+}
+
+/// Read `opacity` as a percentage. Integers are always percent (`1` is 1%);
+/// a float in `0.0..=1.0` is a fraction (`1.0` is 100%, `0.5` is 50%), and a
+/// larger float is percent again (`50.0`).
+fn parse_opacity(obj: &serde_json::Map<String, Value>, default: u8) -> u8 {
+    let Some(v) = obj.get("opacity") else {
+        return default;
+    };
+    if let Some(n) = v.as_u64() {
+        return n.min(100) as u8;
+    }
+    match v.as_f64() {
+        Some(f) if (0.0..=1.0).contains(&f) => (f * 100.0).round() as u8,
+        Some(f) if f > 1.0 => f.min(100.0).round() as u8,
+        _ => default,
+    }
+}
+
+/// Read a `posX`/`posY` divisor. The watermark is placed at `size / pos`, so
+/// only positive values make sense; `0` or negatives (often meant as "left
+/// edge") are reported and replaced by `default` rather than silently.
+fn parse_pos(
+    obj: &serde_json::Map<String, Value>,
+    key: &str,
+    default: f64,
+    label: &str,
+    logger: &mut Logger,
+) -> f64 {
+    let Some(v) = obj.get(key) else {
+        return default;
+    };
+    match v.as_f64() {
+        Some(n) if n > 0.0 && n.is_finite() => n,
+        Some(n) => {
+            logger.log(&format!(
+                "{label}: {key} {n} is not a positive divisor, using {default} (use a large value to move towards the edge)"
+            ));
+            default
+        }
+        None => {
+            logger.log(&format!("{label}: {key} is not a number, using {default}"));
+            default
+        }
+    }
+}
+
 fn parse_watermark(v: &Value, index: usize, logger: &mut Logger) -> Option<Watermark> {
     let obj = v.as_object()?;
     let wm_type = obj.get("type")?.as_str()?;
+    let label = format!("Watermark {}", index + 1);
 
     match wm_type {
         "image" => Some(Watermark::Image {
             path: obj.get("path").and_then(|v| v.as_str()).unwrap_or("watermark1.png").into(),
-            pos_x: obj.get("posX").and_then(|v| v.as_f64()).filter(|&v| v > 0.0).unwrap_or(2.0),
-            pos_y: obj.get("posY").and_then(|v| v.as_f64()).filter(|&v| v > 0.0).unwrap_or(1.2),
-            opacity: obj
-                .get("opacity")
+            pos_x: parse_pos(obj, "posX", 2.0, &label, logger),
+            pos_y: parse_pos(obj, "posY", 1.2, &label, logger),
+            opacity: parse_opacity(obj, 50),
+            frame_index: obj
+                .get("frame_index")
                 .and_then(|v| v.as_u64())
-                .map(|n| n.min(100) as u8)
-                .unwrap_or(50),
+                .map(|n| n.min(u32::MAX as u64) as u32)
+                .unwrap_or(0),
+            z: parse_z(obj),
+            tint_color: obj.get("tint_color").and_then(|v| {
+                let tint = parse_rgb(v);
+                if tint.is_none() {
+                    logger.log(&format!("{label}: tint_color must be [R, G, B], ignoring"));
+                }
+                tint
+            }),
+            flip_h: obj.get("flip_h").map(|v| parse_bool(v, false)).unwrap_or(false),
+            flip_v: obj.get("flip_v").map(|v| parse_bool(v, false)).unwrap_or(false),
         }),
         "text" => {
             let font_color = obj
                 .get("font_color")
-                .and_then(|v| v.as_array())
-                .and_then(|arr| {
-                    if arr.len() == 4 {
-                        let v: Vec<u8> = arr
-                            .iter()
-                            .filter_map(|c| c.as_u64().map(|n| n.min(255) as u8))
-                            .collect();
-                        if v.len() == 4 { Some([v[0], v[1], v[2], v[3]]) } else { None }
-                    } else {
-                        None
-                    }
-                })
+                .and_then(parse_color)
                 .unwrap_or([128, 128, 128, 192]);
 
             let font_weight = obj
@@ -183,19 +824,45 @@ fn parse_watermark(v: &Value, index: usize, logger: &mut Logger) -> Option<Water
                 .unwrap_or("normal")
                 .into();
 
+            // A shadow is enabled by giving it a valid colour.
+            let shadow = obj.get("shadow_color").and_then(parse_color).map(|shadow_color| TextShadow {
+                shadow_color,
+                shadow_offset_x: obj.get("shadow_offset_x").and_then(|v| v.as_f64()).unwrap_or(2.0) as f32,
+                shadow_offset_y: obj.get("shadow_offset_y").and_then(|v| v.as_f64()).unwrap_or(2.0) as f32,
+                shadow_blur: obj.get("shadow_blur").and_then(|v| v.as_f64()).filter(|&v| v >= 0.0).unwrap_or(0.0) as f32,
+            });
+
+            // A gradient replaces font_color and needs both stops.
+            let gradient = match (
+                obj.get("gradient_start").and_then(parse_color),
+                obj.get("gradient_end").and_then(parse_color),
+            ) {
+                (Some(gradient_start), Some(gradient_end)) => Some(TextGradient { gradient_start, gradient_end }),
+                _ if obj.contains_key("gradient_start") || obj.contains_key("gradient_end") => {
+                    logger.log(&format!("{label}: gradient_start and gradient_end must both be colours, using font_color"));
+                    None
+                }
+                _ => None,
+            };
+
             Some(Watermark::Text {
                 content: obj.get("content").and_then(|v| v.as_str()).unwrap_or("Sample Text Watermark").into(),
-                pos_x: obj.get("posX").and_then(|v| v.as_f64()).filter(|&v| v > 0.0).unwrap_or(2.0),
-                pos_y: obj.get("posY").and_then(|v| v.as_f64()).filter(|&v| v > 0.0).unwrap_or(1.5),
-                opacity: obj.get("opacity").and_then(|v| v.as_u64()).map(|n| n.min(100) as u8).unwrap_or(75),
+                pos_x: parse_pos(obj, "posX", 2.0, &label, logger),
+                pos_y: parse_pos(obj, "posY", 1.5, &label, logger),
+                opacity: parse_opacity(obj, 75),
                 font_type: obj.get("font_type").and_then(|v| v.as_str()).unwrap_or("arial.ttf").into(),
                 font_size: obj.get("font_size").and_then(|v| v.as_u64()).map(|n| (n as u32).max(1)).unwrap_or(46),
                 font_color,
                 font_weight,
+                letter_spacing: obj.get("letter_spacing").and_then(|v| v.as_f64()).unwrap_or(0.0) as f32,
+                line_height: obj.get("line_height").and_then(|v| v.as_f64()).filter(|&v| v > 0.0).unwrap_or(1.0) as f32,
+                z: parse_z(obj),
+                shadow,
+                gradient,
             })
         }
         other => {
-            logger.log(&format!("Watermark {}: Unknown type \"{other}\", skipping", index + 1));
+            logger.log(&format!("{label}: Unknown type \"{other}\", skipping"));
             None
         }
     }
@@ -203,13 +870,37 @@ fn parse_watermark(v: &Value, index: usize, logger: &mut Logger) -> Option<Water
 
 // ── Load / Save ──────────────────────────────────────────────────────────────
 
+/// Read just `data_dir` from the config file. Used before the logger exists,
+/// so problems are silently ignored here and reported by `load_config`.
+pub fn read_data_dir(config_path: &Path) -> Option<String> {
+    let content = fs::read_to_string(config_path).ok()?;
+    let value: Value = serde_json::from_str(&content).ok()?;
+    value
+        .get("data_dir")?
+        .as_str()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+/// Read just `date_format` from the config file, falling back to the default
+/// when it is missing or unusable. Like `read_data_dir`, this runs before the
+/// logger exists and stays silent.
+pub fn read_date_format(config_path: &Path) -> String {
+    fs::read_to_string(config_path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+        .and_then(|value| Some(value.get("date_format")?.as_str()?.trim().to_string()))
+        .filter(|f| valid_date_format(f))
+        .unwrap_or_else(|| DEFAULT_DATE_FORMAT.into())
+}
+
 /// Load, validate, and auto-fix configuration from a JSON file.
 pub fn load_config(config_path: &Path, logger: &mut Logger) -> Config {
     let default = Config::default();
 
     if !config_path.exists() {
         logger.log("Config file not found, creating default config");
-        save_config(config_path, &default);
+        save_config(config_path, &default, logger);
         return default;
     }
 
@@ -217,7 +908,7 @@ pub fn load_config(config_path: &Path, logger: &mut Logger) -> Config {
         Ok(c) if !c.trim().is_empty() => c,
         _ => {
             logger.log("Config file empty or unreadable, creating default");
-            save_config(config_path, &default);
+            save_config(config_path, &default, logger);
             return default;
         }
     };
@@ -226,10 +917,12 @@ pub fn load_config(config_path: &Path, logger: &mut Logger) -> Config {
         Ok(v) => v,
         Err(e) => {
             logger.log(&format!("Invalid JSON: {e}. Backing up and resetting."));
-            let backup = format!("{}.bak", config_path.display());
-            let _ = fs::copy(config_path, &backup);
-            logger.log(&format!("Corrupted config backed up to {backup}"));
-            save_config(config_path, &default);
+            if let Some(line) = content.lines().nth(e.line().saturating_sub(1)) {
+                logger.log(&format!("Line {}: {}", e.line(), line.trim_end()));
+                logger.log(&format!("Line {}: {}^", e.line(), " ".repeat(e.column().saturating_sub(1))));
+            }
+            backup_config(config_path, &content, logger);
+            save_config(config_path, &default, logger);
             return default;
         }
     };
@@ -238,21 +931,119 @@ pub fn load_config(config_path: &Path, logger: &mut Logger) -> Config {
         Some(o) => o,
         None => {
             logger.log("Config must be a JSON object, using defaults");
-            save_config(config_path, &default);
+            save_config(config_path, &default, logger);
             return default;
         }
     };
 
+    // A profile for the current monitor layout overrides top-level keys.
+    let profile = select_profile(obj, logger);
+    let mut config = match &profile {
+        Some(overrides) => {
+            let mut merged = obj.clone();
+            merged.extend(
+                overrides
+                    .iter()
+                    .filter(|(k, _)| !BASE_ONLY_KEYS.contains(&k.as_str()))
+                    .map(|(k, v)| (k.clone(), v.clone())),
+            );
+            parse_config(&merged, &default, logger)
+        }
+        None => parse_config(obj, &default, logger),
+    };
+
+    // Missing keys are written from the base config so profile values stay in their profile.
+    if profile.is_some() {
+        let base = parse_config(obj, &default, &mut Logger::silent());
+        save_missing_keys(config_path, obj, &base, &default, logger);
+    } else {
+        save_missing_keys(config_path, obj, &config, &default, logger);
+    }
+
+    // Only after any save, so resolved secrets never end up in the file.
+    resolve_secrets(&mut config, config_path, logger);
+    config
+}
+
+/// Parse and validate every field of a config object, logging what had to be fixed.
+fn parse_config(obj: &serde_json::Map<String, Value>, default: &Config, logger: &mut Logger) -> Config {
     let mut fixed: Vec<String> = Vec::new();
 
+    let data_dir = obj
+        .get("data_dir")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+
+    let source = match obj.get("source").and_then(|v| v.as_str()).map(|s| s.trim().to_ascii_lowercase()) {
+        Some(s) if matches!(s.as_str(), "bing" | "himawari" | "command" | "local") => s,
+        other => {
+            if other.is_some() || obj.contains_key("source") {
+                fixed.push(format!("source (reset to {})", default.source));
+            }
+            default.source.clone()
+        }
+    };
+
+    let source_command = obj
+        .get("source_command")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .to_string();
+
+    let local_folder = obj
+        .get("local_folder")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .to_string();
+    let local_policy = match obj.get("local_policy").and_then(|v| v.as_str()) {
+        Some(s) if matches!(s, "random" | "sequential" | "by-date") => s.to_string(),
+        other => {
+            if other.is_some() || obj.contains_key("local_policy") {
+                fixed.push(format!("local_policy (reset to {})", default.local_policy));
+            }
+            default.local_policy.clone()
+        }
+    };
+
+    let himawari_level = obj.get("himawari_level").map(|v| {
+        match v.as_u64().filter(|n| matches!(n, 1 | 2 | 4 | 8 | 16 | 20)) {
+            Some(n) => n as u32,
+            None => {
+                fixed.push(format!("himawari_level (reset to {})", default.himawari_level));
+                default.himawari_level
+            }
+        }
+    }).unwrap_or(default.himawari_level);
+
     let idx = obj.get("idx").map(|v| {
+        if let Some(val) = v.as_str().and_then(parse_idx_name) {
+            return val;
+        }
         let val = parse_u8(v, 0, 7, default.idx);
         if v.as_u64() != Some(val as u64) { fixed.push(format!("idx (set to {val})")); }
         val
     }).unwrap_or(default.idx);
 
-    let mkt = match obj.get("mkt").and_then(|v| v.as_str()).filter(|s| s.len() >= 2) {
-        Some(s) => s.to_string(),
+    let is_market = |s: &&str| s.len() >= 2;
+    let mut mkt_fallbacks: Vec<String> = obj
+        .get("mkt_fallbacks")
+        .and_then(|v| v.as_array())
+        .map(|arr| arr.iter().filter_map(|v| v.as_str()).filter(is_market).map(String::from).collect())
+        .unwrap_or_default();
+
+    // `mkt` may also be a list: the first entry is primary, the rest are fallbacks.
+    let mkt_list: Vec<String> = match obj.get("mkt") {
+        Some(Value::Array(arr)) => arr.iter().filter_map(|v| v.as_str()).filter(is_market).map(String::from).collect(),
+        Some(v) => v.as_str().filter(is_market).map(String::from).into_iter().collect(),
+        None => vec![],
+    };
+    let mkt = match mkt_list.split_first() {
+        Some((first, rest)) => {
+            mkt_fallbacks.splice(0..0, rest.iter().cloned());
+            first.clone()
+        }
         None => {
             if obj.contains_key("mkt") { fixed.push(format!("mkt (reset to {})", default.mkt)); }
             default.mkt.clone()
@@ -260,8 +1051,57 @@ pub fn load_config(config_path: &Path, logger: &mut Logger) -> Config {
     };
 
     let chk = obj.get("chk").map(|v| parse_bool(v, default.chk)).unwrap_or(default.chk);
+    let fallback_to_previous = obj
+        .get("fallback_to_previous")
+        .map(|v| parse_bool(v, default.fallback_to_previous))
+        .unwrap_or(default.fallback_to_previous);
     let ctd = obj.get("ctd").map(|v| parse_bool(v, default.ctd)).unwrap_or(default.ctd);
+    let mut watermark_days: Vec<String> = Vec::new();
+    if let Some(v) = obj.get("watermark_days") {
+        let entries: Vec<&Value> = match v {
+            Value::Array(arr) => arr.iter().collect(),
+            other => vec![other],
+        };
+        for entry in entries {
+            // Comma-separated strings are accepted too: "mon-fri,sun".
+            let specs: Vec<&str> = entry.as_str().map(|s| s.split(',').collect()).unwrap_or_default();
+            if specs.is_empty() {
+                fixed.push("watermark_days (ignored non-string entry)".into());
+            }
+            for spec in specs.into_iter().filter(|s| !s.trim().is_empty()) {
+                match parse_weekday_spec(spec) {
+                    Some(days) => {
+                        for day in days {
+                            if !watermark_days.iter().any(|d| d == day) {
+                                watermark_days.push(day.into());
+                            }
+                        }
+                    }
+                    None => fixed.push(format!("watermark_days (ignored \"{spec}\")")),
+                }
+            }
+        }
+        watermark_days.sort_by_key(|d| WEEKDAY_NAMES.iter().position(|n| n == d));
+    }
+
+    let set_lock_screen = obj
+        .get("set_lock_screen")
+        .map(|v| parse_bool(v, default.set_lock_screen))
+        .unwrap_or(default.set_lock_screen);
+    let transition = match obj.get("transition").and_then(|v| v.as_str()).map(|s| s.trim().to_ascii_lowercase()) {
+        Some(s) if matches!(s.as_str(), "none" | "fade") => s,
+        other => {
+            if other.is_some() || obj.contains_key("transition") {
+                fixed.push(format!("transition (reset to {})", default.transition));
+            }
+            default.transition.clone()
+        }
+    };
     let wtm = obj.get("wtm").map(|v| parse_bool(v, default.wtm)).unwrap_or(default.wtm);
+    let gamma_correct = obj
+        .get("gamma_correct")
+        .map(|v| parse_bool(v, default.gamma_correct))
+        .unwrap_or(default.gamma_correct);
 
     let retry_delay = obj.get("retry_delay").map(|v| {
         let val = parse_u32_min(v, 1, default.retry_delay);
@@ -274,6 +1114,84 @@ pub fn load_config(config_path: &Path, logger: &mut Logger) -> Config {
         val
     }).unwrap_or(default.retry_count);
 
+    let max_retry_after = obj.get("max_retry_after").map(|v| {
+        let val = parse_u32_min(v, 60, default.max_retry_after);
+        if v.as_u64().is_none_or(|n| n as u32 != val) { fixed.push(format!("max_retry_after (set to {val})")); }
+        val
+    }).unwrap_or(default.max_retry_after);
+
+    let max_bytes_per_sec = obj.get("max_bytes_per_sec").map(|v| {
+        let val = parse_u32_min(v, 0, default.max_bytes_per_sec);
+        if v.as_u64().is_none_or(|n| n as u32 != val) { fixed.push(format!("max_bytes_per_sec (set to {val})")); }
+        val
+    }).unwrap_or(default.max_bytes_per_sec);
+
+    let connect_timeout_secs = obj.get("connect_timeout_secs").map(|v| {
+        let val = parse_u32_min(v, 1, default.connect_timeout_secs);
+        if v.as_u64().is_none_or(|n| n as u32 != val) { fixed.push(format!("connect_timeout_secs (set to {val})")); }
+        val
+    }).unwrap_or(default.connect_timeout_secs);
+    let read_timeout_secs = obj.get("read_timeout_secs").map(|v| {
+        let val = parse_u32_min(v, 1, default.read_timeout_secs);
+        if v.as_u64().is_none_or(|n| n as u32 != val) { fixed.push(format!("read_timeout_secs (set to {val})")); }
+        val
+    }).unwrap_or(default.read_timeout_secs);
+    let total_timeout_secs = obj.get("total_timeout_secs").map(|v| {
+        let val = parse_u32_min(v, 0, default.total_timeout_secs);
+        if v.as_u64().is_none_or(|n| n as u32 != val) { fixed.push(format!("total_timeout_secs (set to {val})")); }
+        val
+    }).unwrap_or(default.total_timeout_secs);
+
+    let ip_version = match obj.get("ip_version").and_then(|v| v.as_str()).map(|s| s.trim().to_ascii_lowercase()) {
+        Some(s) if matches!(s.as_str(), "auto" | "v4" | "v6") => s,
+        other => {
+            if other.is_some() || obj.contains_key("ip_version") {
+                fixed.push(format!("ip_version (reset to {})", default.ip_version));
+            }
+            default.ip_version.clone()
+        }
+    };
+
+    let min_width = obj.get("min_width").map(|v| {
+        let val = parse_u32_min(v, 0, default.min_width);
+        if v.as_u64().is_none_or(|n| n as u32 != val) { fixed.push(format!("min_width (set to {val})")); }
+        val
+    }).unwrap_or(default.min_width);
+    let min_height = obj.get("min_height").map(|v| {
+        let val = parse_u32_min(v, 0, default.min_height);
+        if v.as_u64().is_none_or(|n| n as u32 != val) { fixed.push(format!("min_height (set to {val})")); }
+        val
+    }).unwrap_or(default.min_height);
+
+    let min_interval_hours = obj.get("min_interval_hours").map(|v| {
+        let val = parse_u32_min(v, 0, default.min_interval_hours);
+        if v.as_u64().is_none_or(|n| n as u32 != val) { fixed.push(format!("min_interval_hours (set to {val})")); }
+        val
+    }).unwrap_or(default.min_interval_hours);
+    let pick_from_recent_days = obj.get("pick_from_recent_days").map(|v| {
+        let val = parse_u32_min(v, 0, default.pick_from_recent_days).min(ARCHIVE_DAYS);
+        if v.as_u64().is_none_or(|n| n as u32 != val) { fixed.push(format!("pick_from_recent_days (set to {val})")); }
+        val
+    }).unwrap_or(default.pick_from_recent_days);
+    let archive_max_width = obj.get("archive_max_width").map(|v| {
+        let val = parse_u32_min(v, 0, default.archive_max_width);
+        if v.as_u64().is_none_or(|n| n as u32 != val) { fixed.push(format!("archive_max_width (set to {val})")); }
+        val
+    }).unwrap_or(default.archive_max_width);
+    let min_free_mb = obj.get("min_free_mb").map(|v| {
+        let val = parse_u32_min(v, 0, default.min_free_mb);
+        if v.as_u64().is_none_or(|n| n as u32 != val) { fixed.push(format!("min_free_mb (set to {val})")); }
+        val
+    }).unwrap_or(default.min_free_mb);
+
+    let copyright_watermark = match obj.get("copyright_watermark") {
+        Some(v) => parse_copyright_watermark(v, &default.copyright_watermark, logger).unwrap_or_else(|| {
+            fixed.push("copyright_watermark (invalid format, reset to default)".into());
+            default.copyright_watermark.clone()
+        }),
+        None => default.copyright_watermark.clone(),
+    };
+
     let watermarks = if let Some(arr) = obj.get("watermarks").and_then(|v| v.as_array()) {
         arr.iter()
             .enumerate()
@@ -286,6 +1204,53 @@ pub fn load_config(config_path: &Path, logger: &mut Logger) -> Config {
         default.watermarks.clone()
     };
 
+    let safe_area = match obj.get("safe_area") {
+        Some(v) => parse_safe_area(v).unwrap_or_else(|| {
+            fixed.push("safe_area (invalid format, reset to default)".into());
+            SafeArea::default()
+        }),
+        None => default.safe_area,
+    };
+
+    let aspect_crop = obj
+        .get("aspect_crop")
+        .and_then(|v| v.as_str())
+        .map(|s| s.trim().to_string())
+        .unwrap_or_default();
+
+    let adjustments = match obj.get("adjustments") {
+        Some(v) => parse_adjustments(v).unwrap_or_else(|| {
+            fixed.push("adjustments (invalid format, reset to default)".into());
+            Adjustments::default()
+        }),
+        None => Adjustments::default(),
+    };
+
+    let caption_bar = match obj.get("caption_bar") {
+        Some(v) => parse_caption_bar(v).unwrap_or_else(|| {
+            fixed.push("caption_bar (invalid format, reset to default)".into());
+            CaptionBar::default()
+        }),
+        None => CaptionBar::default(),
+    };
+
+    let date_format = match obj.get("date_format").and_then(|v| v.as_str()).map(str::trim) {
+        Some(f) if valid_date_format(f) => f.to_string(),
+        other => {
+            if other.is_some() || obj.contains_key("date_format") {
+                fixed.push(format!("date_format (not a usable folder name, reset to {DEFAULT_DATE_FORMAT})"));
+            }
+            default.date_format.clone()
+        }
+    };
+
+    let filename_pattern = obj.get("filename_pattern").map(|v| {
+        parse_filename_pattern(v).unwrap_or_else(|| {
+            fixed.push(format!("filename_pattern (not a legal file name, reset to {DEFAULT_FILENAME_PATTERN})"));
+            DEFAULT_FILENAME_PATTERN.into()
+        })
+    }).unwrap_or_else(|| default.filename_pattern.clone());
+
     let post_execution_apps = obj
         .get("post_execution_apps")
         .and_then(|v| v.as_array())
@@ -295,42 +1260,354 @@ pub fn load_config(config_path: &Path, logger: &mut Logger) -> Config {
     let copy_to_paths = obj
         .get("copy_to_paths")
         .and_then(|v| v.as_array())
-        .map(|arr| arr.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+        .map(|arr| arr.iter().filter_map(parse_copy_target).collect())
         .unwrap_or_default();
 
+    let history = obj.get("history").map(|v| parse_bool(v, default.history)).unwrap_or(default.history);
+
+    let profiles = match obj.get("profiles") {
+        Some(Value::Object(map)) => map.clone(),
+        Some(_) => {
+            fixed.push("profiles (not an object, ignored)".into());
+            serde_json::Map::new()
+        }
+        None => serde_json::Map::new(),
+    };
+
+    let update_url = obj
+        .get("update_url")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+
     if !fixed.is_empty() {
         logger.log(&format!("Fixed config values: {}", fixed.join(", ")));
     }
 
-    let config = Config {
-        idx, mkt, chk, ctd, wtm, retry_delay, retry_count,
-        watermarks, post_execution_apps, copy_to_paths,
+    Config {
+        data_dir, source, source_command, local_folder, local_policy, himawari_level,
+        idx, mkt, mkt_fallbacks, fallback_to_previous, chk, min_interval_hours, pick_from_recent_days, archive_max_width, min_free_mb,
+        ctd, set_lock_screen, transition, wtm, watermark_days,
+        retry_delay, retry_count, max_retry_after, max_bytes_per_sec,
+        connect_timeout_secs, read_timeout_secs, total_timeout_secs, ip_version, min_width, min_height,
+        gamma_correct, aspect_crop, copyright_watermark, watermarks, safe_area, adjustments, caption_bar,
+        date_format, filename_pattern, post_execution_apps, copy_to_paths, history, update_url, profiles,
+    }
+}
+
+/// Keys a profile can't override. `data_dir` and `date_format` are read from the
+/// top level before the config is loaded (to place the log and today's folder),
+/// so a per-layout value would split the data between two places.
+const BASE_ONLY_KEYS: [&str; 3] = ["profiles", "data_dir", "date_format"];
+
+/// The `profiles` entry matching the current monitor layout, if any.
+fn select_profile(
+    obj: &serde_json::Map<String, Value>,
+    logger: &mut Logger,
+) -> Option<serde_json::Map<String, Value>> {
+    let profiles = obj.get("profiles")?.as_object().filter(|p| !p.is_empty())?;
+    let Some(sizes) = monitor_sizes() else {
+        logger.log("Monitor layout unavailable, using base config");
+        return None;
     };
+    let layout = layout_signature(&sizes);
+    let mut matched = None;
+    for (key, value) in profiles {
+        match (parse_layout_signature(key), value.as_object()) {
+            (Some(signature), Some(overrides)) => {
+                for base_only in overrides.keys().filter(|k| BASE_ONLY_KEYS.contains(&k.as_str())) {
+                    logger.log(&format!("Profile \"{key}\": {base_only} can only be set at the top level, ignored"));
+                }
+                if signature == layout {
+                    matched = Some((key, overrides));
+                }
+            }
+            _ => logger.log(&format!("Profile \"{key}\" ignored: expect a key like \"1920x1080+2560x1440\" and an object value")),
+        }
+    }
+    match matched {
+        Some((key, overrides)) => {
+            logger.log(&format!(
+                "Monitor layout {layout} ({} monitors), using profile \"{key}\": {}",
+                sizes.len(),
+                Value::Object(overrides.clone())
+            ));
+            Some(overrides.clone())
+        }
+        None => {
+            logger.log(&format!("Monitor layout {layout} ({} monitors) has no profile, using base config", sizes.len()));
+            None
+        }
+    }
+}
 
-    // Detect and fill missing keys
-    let default_json = match serde_json::to_value(&default) {
+/// Write the config back with defaults for keys the file doesn't have yet.
+fn save_missing_keys(
+    config_path: &Path,
+    obj: &serde_json::Map<String, Value>,
+    config: &Config,
+    default: &Config,
+    logger: &mut Logger,
+) {
+    let default_json = match serde_json::to_value(default) {
         Ok(v) => v,
-        Err(_) => return config,
+        Err(_) => return,
     };
-    let mut needs_update = false;
-    if let Some(default_obj) = default_json.as_object() {
-        for key in default_obj.keys() {
-            if !obj.contains_key(key) {
-                logger.log(&format!("Missing config key \"{key}\", added with default value"));
-                needs_update = true;
+    let missing: Vec<&String> = default_json
+        .as_object()
+        .map(|d| d.keys().filter(|k| !obj.contains_key(*k)).collect())
+        .unwrap_or_default();
+    if missing.is_empty() {
+        return;
+    }
+    // A read-only config would fail the same save on every run; say so once a day instead.
+    if is_read_only(config_path) {
+        logger.log_once(&format!(
+            "Config file is read-only, using defaults for missing keys: {}",
+            missing.iter().map(|k| k.as_str()).collect::<Vec<_>>().join(", ")
+        ));
+        return;
+    }
+    for key in &missing {
+        logger.log(&format!("Missing config key \"{key}\", added with default value"));
+    }
+    if save_config(config_path, config, logger) {
+        logger.log("Config file updated with missing keys");
+    }
+}
+
+/// Resolve secret indirection in `value`: a whole value of `@file:<path>` is
+/// replaced by the trimmed contents of that file (relative to the config
+/// directory), and every `${NAME}` by the environment variable `NAME`.
+/// Returns `None` when `value` uses neither form.
+fn resolve_secret(value: &str, config_dir: &Path, field: &str, logger: &mut Logger) -> Option<String> {
+    if let Some(file) = value.strip_prefix("@file:") {
+        let path = config_dir.join(file.trim());
+        return match fs::read_to_string(&path) {
+            Ok(s) => {
+                logger.redact(s.trim());
+                logger.log(&format!("{field}: secret read from {}", path.display()));
+                Some(s.trim().to_string())
             }
+            Err(e) => {
+                logger.log(&format!("{field}: failed to read secret file {}: {e}", path.display()));
+                Some(String::new())
+            }
+        };
+    }
+    if !value.contains("${") {
+        return None;
+    }
+
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start + 2..].find('}') else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let name = &rest[start + 2..start + 2 + len];
+        match env::var(name) {
+            Ok(v) => {
+                logger.redact(&v);
+                logger.log(&format!("{field}: secret resolved from ${{{name}}}"));
+                out.push_str(&v);
+            }
+            Err(_) => logger.log(&format!("{field}: environment variable {name} is not set")),
         }
+        rest = &rest[start + 3 + len..];
     }
-    if needs_update {
-        save_config(config_path, &config);
-        logger.log("Config file updated with missing keys");
+    out.push_str(rest);
+    Some(out)
+}
+
+/// Apply [`resolve_secret`] to `update_url`, the one field meant to carry
+/// credentials. `source_command` is left alone: it is a command line with its
+/// own `%VAR%` expansion, and `${...}` there belongs to the shell.
+fn resolve_secrets(config: &mut Config, config_path: &Path, logger: &mut Logger) {
+    let config_dir = config_path.parent().unwrap_or(Path::new("."));
+    if let Some(resolved) = resolve_secret(&config.update_url, config_dir, "update_url", logger) {
+        config.update_url = resolved;
     }
+}
 
-    config
+/// Write `config` to `path`. Returns false (after logging why) when it
+/// couldn't be written, e.g. because the file is read-only.
+fn save_config(path: &Path, config: &Config, logger: &mut Logger) -> bool {
+    let json = match serde_json::to_string_pretty(config) {
+        Ok(j) => j,
+        Err(e) => {
+            logger.log(&format!("Failed to serialize config: {e}"));
+            return false;
+        }
+    };
+    match fs::write(path, json) {
+        Ok(_) => true,
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            logger.log(&format!("Config file {} is read-only, changes not saved", path.display()));
+            false
+        }
+        Err(e) => {
+            logger.log(&format!("Failed to save config: {e}"));
+            false
+        }
+    }
+}
+
+/// Copy an unparseable config to `config.<epoch>.bak`, keeping the newest
+/// `CONFIG_BACKUPS`. Skipped when the newest backup already holds the same
+/// content, so a config that keeps failing doesn't push out older versions.
+fn backup_config(config_path: &Path, content: &str, logger: &mut Logger) {
+    let dir = config_path.parent().unwrap_or(Path::new("."));
+    let stem = config_path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut backups: Vec<(i64, std::path::PathBuf)> = fs::read_dir(dir)
+        .map(|rd| {
+            rd.flatten()
+                .filter_map(|e| {
+                    let name = e.file_name().to_string_lossy().into_owned();
+                    let epoch = name.strip_prefix(&format!("{stem}."))?.strip_suffix(".bak")?.parse().ok()?;
+                    Some((epoch, e.path()))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    backups.sort_by_key(|(epoch, _)| *epoch);
+
+    let newest_same = backups.last().is_some_and(|(_, path)| {
+        fs::metadata(path).is_ok_and(|m| m.len() == content.len() as u64)
+            && fs::read(path).is_ok_and(|b| b == content.as_bytes())
+    });
+    if newest_same {
+        logger.log("Corrupted config matches the latest backup, not backing up again");
+        return;
+    }
+
+    let backup = dir.join(format!("{stem}.{}.bak", chrono::Utc::now().timestamp()));
+    match fs::write(&backup, content) {
+        Ok(()) => {
+            logger.log(&format!("Corrupted config backed up to {}", backup.display()));
+            backups.push((i64::MAX, backup));
+        }
+        Err(e) => logger.log(&format!("Failed to back up corrupted config: {e}")),
+    }
+    let excess = backups.len().saturating_sub(CONFIG_BACKUPS);
+    for (_, old) in backups.drain(..excess) {
+        if fs::remove_file(&old).is_ok() {
+            logger.log(&format!("Removed old config backup {}", old.display()));
+        }
+    }
+}
+
+fn is_read_only(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|m| m.permissions().readonly())
 }
 
-fn save_config(path: &Path, config: &Config) {
-    if let Ok(json) = serde_json::to_string_pretty(config) {
-        let _ = fs::write(path, json);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(json: &str) -> serde_json::Map<String, Value> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn opacity_accepts_fractions_and_percentages() {
+        let opacity = |json: &str| parse_opacity(&object(json), 70);
+        // Integers are percentages, floats up to 1.0 are fractions.
+        assert_eq!(opacity(r#"{"opacity": 1}"#), 1);
+        assert_eq!(opacity(r#"{"opacity": 1.0}"#), 100);
+        assert_eq!(opacity(r#"{"opacity": 0.5}"#), 50);
+        assert_eq!(opacity(r#"{"opacity": 50}"#), 50);
+        assert_eq!(opacity(r#"{"opacity": 100}"#), 100);
+        assert_eq!(opacity(r#"{"opacity": 0}"#), 0);
+    }
+
+    #[test]
+    fn opacity_out_of_range() {
+        let opacity = |json: &str| parse_opacity(&object(json), 70);
+        assert_eq!(opacity(r#"{"opacity": 150}"#), 100);
+        assert_eq!(opacity(r#"{"opacity": 250.5}"#), 100);
+        assert_eq!(opacity(r#"{"opacity": -1}"#), 70);
+        assert_eq!(opacity(r#"{"opacity": -0.5}"#), 70);
+        assert_eq!(opacity(r#"{"opacity": "half"}"#), 70);
+        assert_eq!(opacity("{}"), 70);
+    }
+
+    /// Run `f` with a logger writing to a scratch file and return what it logged.
+    fn logged<T>(name: &str, f: impl FnOnce(&mut Logger) -> T) -> (T, String) {
+        let path = env::temp_dir().join(format!("auto-wallpaper-{name}-{}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let result = f(&mut Logger::new(&path));
+        let log = fs::read_to_string(&path).unwrap_or_default();
+        let _ = fs::remove_file(&path);
+        (result, log)
+    }
+
+    #[test]
+    fn pos_accepts_positive_divisors() {
+        let (pos, log) = logged("pos-ok", |logger| {
+            [r#"{"posX": 1}"#, r#"{"posX": 2.5}"#, "{}"]
+                .map(|json| parse_pos(&object(json), "posX", 2.0, "Watermark 1", logger))
+        });
+        assert_eq!(pos, [1.0, 2.5, 2.0]);
+        assert!(log.is_empty(), "unexpected log: {log}");
+    }
+
+    #[test]
+    fn pos_falls_back_on_zero_negative_and_text() {
+        for (json, message) in [
+            (r#"{"posY": 0}"#, "Watermark 1: posY 0 is not a positive divisor, using 2"),
+            (r#"{"posY": -3}"#, "Watermark 1: posY -3 is not a positive divisor, using 2"),
+            (r#"{"posY": "left"}"#, "Watermark 1: posY is not a number, using 2"),
+        ] {
+            let (pos, log) = logged("pos-bad", |logger| parse_pos(&object(json), "posY", 2.0, "Watermark 1", logger));
+            assert_eq!(pos, 2.0, "{json}");
+            assert!(log.contains(message), "{json}: {log}");
+        }
+    }
+
+    #[test]
+    fn read_only_config_reported_once_a_day() {
+        let dir = env::temp_dir().join(format!("auto-wallpaper-read-only-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("config.json");
+        fs::write(&config_path, r#"{"wtm": true}"#).unwrap();
+        let mut permissions = fs::metadata(&config_path).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&config_path, permissions).unwrap();
+
+        // Two runs on the same day share a log file.
+        let log_path = dir.join("today.log");
+        for _ in 0..2 {
+            load_config(&config_path, &mut Logger::new(&log_path));
+        }
+        let log = fs::read_to_string(&log_path).unwrap();
+        assert_eq!(log.matches("Config file is read-only, using defaults for missing keys").count(), 1, "{log}");
+        assert_eq!(fs::read_to_string(&config_path).unwrap(), r#"{"wtm": true}"#);
+
+        let mut permissions = fs::metadata(&config_path).unwrap().permissions();
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        let _ = fs::set_permissions(&config_path, permissions);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn secrets_leave_source_command_to_the_shell() {
+        env::set_var("AW_TEST_SECRET", "hunter2");
+        let mut config = Config {
+            source_command: "fetch --home ${HOME} --key ${AW_TEST_SECRET}".into(),
+            update_url: "https://example.com/latest?key=${AW_TEST_SECRET}".into(),
+            ..Default::default()
+        };
+        let (_, log) = logged("secrets", |logger| {
+            resolve_secrets(&mut config, Path::new("config.json"), logger);
+            logger.log(&config.update_url);
+        });
+        assert_eq!(config.source_command, "fetch --home ${HOME} --key ${AW_TEST_SECRET}");
+        assert_eq!(config.update_url, "https://example.com/latest?key=hunter2");
+        assert!(!log.contains("hunter2"), "{log}");
     }
 }
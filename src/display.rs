@@ -0,0 +1,123 @@
+// ── Monitor layout ───────────────────────────────────────────────────────────
+
+#[cfg(windows)]
+const ENUM_CURRENT_SETTINGS: u32 = 0xFFFF_FFFF;
+
+#[cfg(windows)]
+#[repr(C)]
+struct Rect {
+    left: i32,
+    top: i32,
+    right: i32,
+    bottom: i32,
+}
+
+#[cfg(windows)]
+#[repr(C)]
+struct MonitorInfoExW {
+    cb_size: u32,
+    rc_monitor: Rect,
+    rc_work: Rect,
+    dw_flags: u32,
+    sz_device: [u16; 32],
+}
+
+/// `DEVMODEW` with the printer/display unions flattened to plain fields.
+#[cfg(windows)]
+#[repr(C)]
+struct DevModeW {
+    dm_device_name: [u16; 32],
+    dm_spec_version: u16,
+    dm_driver_version: u16,
+    dm_size: u16,
+    dm_driver_extra: u16,
+    dm_fields: u32,
+    dm_position_union: [u32; 4],
+    dm_color: i16,
+    dm_duplex: i16,
+    dm_y_resolution: i16,
+    dm_tt_option: i16,
+    dm_collate: i16,
+    dm_form_name: [u16; 32],
+    dm_log_pixels: u16,
+    dm_bits_per_pel: u32,
+    dm_pels_width: u32,
+    dm_pels_height: u32,
+    dm_display_flags: u32,
+    dm_display_frequency: u32,
+    dm_tail: [u32; 8],
+}
+
+#[cfg(windows)]
+type MonitorEnumProc = unsafe extern "system" fn(isize, isize, *mut Rect, isize) -> i32;
+
+#[cfg(windows)]
+#[link(name = "user32")]
+extern "system" {
+    fn EnumDisplayMonitors(hdc: isize, lprcClip: *const Rect, lpfnEnum: MonitorEnumProc, dwData: isize) -> i32;
+    fn GetMonitorInfoW(hMonitor: isize, lpmi: *mut MonitorInfoExW) -> i32;
+    fn EnumDisplaySettingsW(lpszDeviceName: *const u16, iModeNum: u32, lpDevMode: *mut DevModeW) -> i32;
+}
+
+/// Physical resolution of one monitor. The process isn't DPI aware, so the
+/// monitor rectangle may be scaled; the display mode is the real size.
+#[cfg(windows)]
+unsafe fn monitor_size(monitor: isize, rect: &Rect) -> (u32, u32) {
+    let scaled = ((rect.right - rect.left).max(0) as u32, (rect.bottom - rect.top).max(0) as u32);
+    let mut info: MonitorInfoExW = std::mem::zeroed();
+    info.cb_size = std::mem::size_of::<MonitorInfoExW>() as u32;
+    if GetMonitorInfoW(monitor, &mut info) == 0 {
+        return scaled;
+    }
+    let mut mode: DevModeW = std::mem::zeroed();
+    mode.dm_size = std::mem::size_of::<DevModeW>() as u16;
+    if EnumDisplaySettingsW(info.sz_device.as_ptr(), ENUM_CURRENT_SETTINGS, &mut mode) == 0 {
+        return scaled;
+    }
+    (mode.dm_pels_width, mode.dm_pels_height)
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn collect_monitor(monitor: isize, _hdc: isize, rect: *mut Rect, data: isize) -> i32 {
+    let sizes = &mut *(data as *mut Vec<(u32, u32)>);
+    if let Some(rect) = rect.as_ref() {
+        sizes.push(monitor_size(monitor, rect));
+    }
+    1
+}
+
+/// Resolutions of the attached monitors, or `None` if they can't be listed.
+#[cfg(windows)]
+pub fn monitor_sizes() -> Option<Vec<(u32, u32)>> {
+    let mut sizes: Vec<(u32, u32)> = Vec::new();
+    let ok = unsafe {
+        EnumDisplayMonitors(0, std::ptr::null(), collect_monitor, &mut sizes as *mut Vec<(u32, u32)> as isize)
+    };
+    (ok != 0 && !sizes.is_empty()).then_some(sizes)
+}
+
+#[cfg(not(windows))]
+pub fn monitor_sizes() -> Option<Vec<(u32, u32)>> {
+    None
+}
+
+/// Order-independent layout signature such as `1920x1080+2560x1440`.
+pub fn layout_signature(sizes: &[(u32, u32)]) -> String {
+    let mut sizes = sizes.to_vec();
+    sizes.sort_unstable();
+    sizes.iter().map(|(w, h)| format!("{w}x{h}")).collect::<Vec<_>>().join("+")
+}
+
+/// Normalise a `profiles` key written by hand (spaces, `×`, any order) into a
+/// signature comparable with [`layout_signature`].
+pub fn parse_layout_signature(key: &str) -> Option<String> {
+    let sizes = key
+        .split('+')
+        .map(|part| {
+            let part = part.trim().to_ascii_lowercase().replace('×', "x");
+            let (w, h) = part.split_once('x')?;
+            Some((w.trim().parse::<u32>().ok()?, h.trim().parse::<u32>().ok()?))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(layout_signature(&sizes))
+}
@@ -1,32 +1,126 @@
+use std::fmt;
 use std::fs;
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, ToSocketAddrs};
 use std::path::Path;
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use chrono::{DateTime, Utc};
+
+use crate::config::Config;
 use crate::logger::Logger;
+use crate::shutdown;
 
 //
 // ===================== Configuration =====================
 //
 
-/// Network connect/read timeout per attempt.
-const TIMEOUT: Duration = Duration::from_secs(30);
-
 /// Max sleep seconds between retries (backoff cap).
 /// - For network/transport errors: reaching this cap still allows further retries (sleep stays capped).
 /// - For HTTP status errors: once backoff reaches/exceeds this cap, retries stop (as requested).
 const MAX_RETRY_SLEEP_SECS: u64 = 60;
 
+/// Size of each read from the response body.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Address family used for connections.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum IpVersion {
+    /// System resolver order.
+    Auto,
+    V4,
+    V6,
+}
+
+impl IpVersion {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "v4" => Self::V4,
+            "v6" => Self::V6,
+            _ => Self::Auto,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::V4 => "v4",
+            Self::V6 => "v6",
+        }
+    }
+}
+
+/// Per-download settings taken from the config.
+#[derive(Copy, Clone, Debug)]
+pub struct DownloadOptions {
+    pub retry_delay: u32,
+    pub retry_count: u32,
+    /// Cap on honoured `Retry-After` hints, in seconds.
+    pub max_retry_after: u32,
+    /// Bandwidth cap in bytes per second; `0` means unlimited.
+    pub max_bytes_per_sec: u32,
+    pub ip_version: IpVersion,
+    pub connect_timeout_secs: u32,
+    /// Longest gap between received bytes, in seconds.
+    pub read_timeout_secs: u32,
+    /// Bound on all downloads of a run including retries; `0` means unbounded.
+    pub total_timeout_secs: u32,
+    /// When `total_timeout_secs` runs out. Fixed once in `from_config`, so
+    /// every `download_file` of a run (e.g. all himawari tiles) shares it.
+    pub deadline: Option<Instant>,
+}
+
+impl DownloadOptions {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            retry_delay: config.retry_delay,
+            retry_count: config.retry_count,
+            max_retry_after: config.max_retry_after,
+            max_bytes_per_sec: config.max_bytes_per_sec,
+            ip_version: IpVersion::from_name(&config.ip_version),
+            connect_timeout_secs: config.connect_timeout_secs,
+            read_timeout_secs: config.read_timeout_secs,
+            total_timeout_secs: config.total_timeout_secs,
+            deadline: (config.total_timeout_secs > 0)
+                .then(|| Instant::now() + Duration::from_secs(config.total_timeout_secs as u64)),
+        }
+    }
+}
+
 //
 // ===================== Implementation =====================
 //
 
+/// Why a download failed, after any retries.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
-enum RetryKind {
-    Network,   // ureq::Error::Transport(...)
-    HttpStatus, // ureq::Error::Status(...)
-    Io,        // read/write filesystem errors
+pub enum DownloadError {
+    /// `retry_count` is 0, so nothing was attempted.
+    Disabled,
+    /// DNS, connect, TLS or timeout failure (`ureq::Error::Transport`).
+    Network,
+    /// The server answered with a non-2xx status.
+    HttpStatus(u16),
+    /// Reading the body or writing the file failed.
+    Io,
+    /// A shutdown was requested (Ctrl-C / SIGTERM).
+    Cancelled,
+    /// The run's `total_timeout_secs` budget is spent.
+    Timeout,
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Disabled => write!(f, "downloads disabled (retry_count=0)"),
+            Self::Network => write!(f, "network error"),
+            Self::HttpStatus(code) => write!(f, "HTTP status {code}"),
+            Self::Io => write!(f, "I/O error"),
+            Self::Cancelled => write!(f, "cancelled by shutdown request"),
+            Self::Timeout => write!(f, "total timeout reached"),
+        }
+    }
 }
 
 #[inline]
@@ -61,36 +155,281 @@ fn compute_backoff_secs(base_delay: u64, attempt: u32) -> u64 {
     base_delay.saturating_mul(exp)
 }
 
+/// A failed attempt, as far as the retry decision is concerned.
+#[derive(Copy, Clone, Debug)]
+struct Failure {
+    /// Zero-based attempt number.
+    attempt: u32,
+    kind: DownloadError,
+    retryable: bool,
+    /// Server `Retry-After` hint in seconds.
+    retry_after: Option<u64>,
+}
+
+/// Why retrying ends.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum StopReason {
+    /// The failure can't be fixed by trying again (e.g. 404).
+    NotRetryable,
+    /// All `retry_count` attempts are used.
+    Exhausted,
+    /// HTTP status retries stop once the backoff reaches `MAX_RETRY_SLEEP_SECS`.
+    BackoffCap,
+    /// The server's `Retry-After` is longer than `max_retry_after`; retrying
+    /// sooner would only be refused again.
+    RetryAfterCap,
+    /// The next wait would run past `total_timeout_secs`.
+    TotalTimeout,
+}
+
+/// What to do after a failed attempt.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Step {
+    /// Sleep `sleep` seconds, then try again; `server_hint` is set when the
+    /// wait comes from `Retry-After`.
+    Retry { sleep: u64, server_hint: Option<u64> },
+    Stop(StopReason),
+}
+
+/// Retry rules of `download_file`, kept free of I/O and sleeping.
+#[derive(Copy, Clone, Debug)]
+struct RetryPolicy {
+    retry_count: u32,
+    /// Backoff base in seconds (at least 1).
+    base_delay: u64,
+    /// Longest honoured `Retry-After`, in seconds.
+    retry_after_cap: u64,
+}
+
+impl RetryPolicy {
+    /// Decide the next step after `failure`, with `time_left` until the
+    /// total timeout (`None` when unbounded).
+    fn next_step(&self, failure: &Failure, time_left: Option<Duration>) -> Step {
+        if !failure.retryable {
+            return Step::Stop(StopReason::NotRetryable);
+        }
+        if failure.attempt + 1 >= self.retry_count {
+            return Step::Stop(StopReason::Exhausted);
+        }
+        let out_of_time = |secs: u64| time_left.is_some_and(|left| Duration::from_secs(secs) >= left);
+
+        // A server hint replaces the computed backoff and the HTTP cap rule below.
+        if let Some(hint) = failure.retry_after {
+            if hint > self.retry_after_cap {
+                return Step::Stop(StopReason::RetryAfterCap);
+            }
+            if out_of_time(hint) {
+                return Step::Stop(StopReason::TotalTimeout);
+            }
+            return Step::Retry { sleep: hint, server_hint: Some(hint) };
+        }
+
+        let backoff = compute_backoff_secs(self.base_delay, failure.attempt);
+        // Once the backoff reaches the cap, network errors keep retrying
+        // (sleep stays capped) while HTTP status errors stop.
+        if backoff >= MAX_RETRY_SLEEP_SECS && matches!(failure.kind, DownloadError::HttpStatus(_)) {
+            return Step::Stop(StopReason::BackoffCap);
+        }
+        let capped = backoff.min(MAX_RETRY_SLEEP_SECS);
+        if out_of_time(capped) {
+            return Step::Stop(StopReason::TotalTimeout);
+        }
+        Step::Retry { sleep: capped, server_hint: None }
+    }
+}
+
+/// Parse a `Retry-After` value, either delay-seconds or an HTTP-date,
+/// into seconds from `now`. Dates in the past mean "retry immediately".
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<u64> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(secs);
+    }
+    let when = DateTime::parse_from_rfc2822(value).ok()?;
+    Some((when.with_timezone(&Utc) - now).num_seconds().max(0) as u64)
+}
+
+/// Resolver that keeps only addresses of the preferred family and records
+/// what it returned so the caller can log it.
+struct FamilyResolver {
+    version: IpVersion,
+    resolved: Arc<Mutex<Vec<SocketAddr>>>,
+}
+
+impl ureq::Resolver for FamilyResolver {
+    fn resolve(&self, netloc: &str) -> io::Result<Vec<SocketAddr>> {
+        let addrs: Vec<SocketAddr> = netloc
+            .to_socket_addrs()?
+            .filter(|a| match self.version {
+                IpVersion::Auto => true,
+                IpVersion::V4 => a.is_ipv4(),
+                IpVersion::V6 => a.is_ipv6(),
+            })
+            .collect();
+        if addrs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no {} address for {netloc}", self.version.name()),
+            ));
+        }
+        if let Ok(mut r) = self.resolved.lock() {
+            r.clone_from(&addrs);
+        }
+        Ok(addrs)
+    }
+}
+
+/// Token bucket limiting read throughput. A rate of `0` disables throttling.
+struct Throttle {
+    rate: u64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl Throttle {
+    fn new(rate: u32) -> Self {
+        Self {
+            rate: rate as u64,
+            tokens: rate as f64,
+            last: Instant::now(),
+        }
+    }
+
+    /// Largest read that fits the rate, so sleeps stay short and smooth.
+    fn chunk_size(&self) -> usize {
+        if self.rate == 0 {
+            CHUNK_SIZE
+        } else {
+            (self.rate as usize).clamp(1, CHUNK_SIZE)
+        }
+    }
+
+    /// Account for `n` bytes read, sleeping if the bucket is overdrawn.
+    /// Sleeping happens between reads, so the per-read timeout is unaffected.
+    fn consume(&mut self, n: usize) {
+        if self.rate == 0 {
+            return;
+        }
+        let now = Instant::now();
+        let refill = now.duration_since(self.last).as_secs_f64() * self.rate as f64;
+        // Burst capacity is one second worth of data.
+        self.tokens = (self.tokens + refill).min(self.rate as f64) - n as f64;
+        self.last = now;
+        if self.tokens < 0.0 {
+            thread::sleep(Duration::from_secs_f64(-self.tokens / self.rate as f64));
+        }
+    }
+}
+
+/// Read the whole response body in chunks, honouring the throttle.
+fn read_body(mut reader: impl Read, buf: &mut Vec<u8>, throttle: &mut Throttle) -> std::io::Result<()> {
+    let mut chunk = vec![0u8; throttle.chunk_size()];
+    loop {
+        match reader.read(&mut chunk) {
+            Ok(0) => return Ok(()),
+            Ok(_) if shutdown::requested() => return Err(io::Error::other("shutdown requested")),
+            Ok(n) => {
+                buf.extend_from_slice(&chunk[..n]);
+                throttle.consume(n);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
 /// Download a file from `url` to `path` with retry logic.
 pub fn download_file(
     url: &str,
     path: &Path,
     logger: &mut Logger,
-    retry_delay: u32,
-    retry_count: u32,
-) -> bool {
+    options: &DownloadOptions,
+) -> Result<(), DownloadError> {
+    let DownloadOptions {
+        retry_delay,
+        retry_count,
+        max_retry_after,
+        max_bytes_per_sec,
+        ip_version,
+        connect_timeout_secs,
+        read_timeout_secs,
+        total_timeout_secs,
+        deadline,
+    } = *options;
+    let policy = RetryPolicy {
+        retry_count,
+        base_delay: retry_delay.max(1) as u64,
+        retry_after_cap: (max_retry_after as u64).max(MAX_RETRY_SLEEP_SECS),
+    };
+
     if retry_count == 0 {
         logger.log(&format!("retry_count=0, refusing to download {url}"));
-        return false;
+        return Err(DownloadError::Disabled);
     }
 
+    let resolved = Arc::new(Mutex::new(Vec::new()));
     let agent = ureq::AgentBuilder::new()
-        .timeout_connect(TIMEOUT)
-        .timeout_read(TIMEOUT)
+        .timeout_connect(Duration::from_secs(connect_timeout_secs.max(1) as u64))
+        .timeout_read(Duration::from_secs(read_timeout_secs.max(1) as u64))
+        .resolver(FamilyResolver { version: ip_version, resolved: Arc::clone(&resolved) })
         .build();
 
-    let base_delay = retry_delay.max(1) as u64;
+    let time_left = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+    if time_left.is_some_and(|t| t.is_zero()) {
+        logger.log(&format!("Total timeout of {total_timeout_secs}s already used up, not downloading {url}"));
+        return Err(DownloadError::Timeout);
+    }
+    logger.log(&format!(
+        "Timeouts for {url}: connect={}s, read={}s, total={}",
+        connect_timeout_secs.max(1),
+        read_timeout_secs.max(1),
+        match time_left {
+            Some(t) => format!("{}s left of {total_timeout_secs}s", t.as_secs()),
+            None => "unbounded".into(),
+        }
+    ));
+
+    if max_bytes_per_sec > 0 {
+        logger.log(&format!(
+            "Download throttled to {max_bytes_per_sec} bytes/s ({:.1} KB/s) for {url}",
+            max_bytes_per_sec as f64 / 1024.0
+        ));
+    }
+
+    let mut last_error = DownloadError::Network;
+    // Drop any partial temp file and report the cancellation.
+    let cancel = |logger: &mut Logger| {
+        let _ = fs::remove_file(path.with_extension("tmp"));
+        logger.log(&format!("Shutdown requested, cancelled download of {url}"));
+        Err(DownloadError::Cancelled)
+    };
 
     for attempt in 0..retry_count {
+        if shutdown::requested() {
+            return cancel(logger);
+        }
         let attempt_no = attempt + 1;
 
         // Execute one attempt
-        let outcome: Result<(), (RetryKind, String, bool)> = match agent.get(url).call() {
+        // Err: (kind, message, should_retry, server Retry-After hint in seconds)
+        let mut request = agent.get(url);
+        if let Some(d) = deadline {
+            // The overall ureq timeout also covers reading the body.
+            request = request.timeout(d.saturating_duration_since(Instant::now()));
+        }
+        let response = request.call();
+        if attempt == 0 {
+            if let Some(addr) = resolved.lock().ok().and_then(|r| r.first().copied()) {
+                logger.log(&format!("{url} resolved to {addr} (ip_version={})", ip_version.name()));
+            }
+        }
+        let outcome: Result<(), (DownloadError, String, bool, Option<u64>)> = match response {
             Ok(resp) => {
                 // 2xx only (ureq treats non-2xx as Err(Status))
                 let mut buf = Vec::new();
+                let mut throttle = Throttle::new(max_bytes_per_sec);
 
-                match resp.into_reader().read_to_end(&mut buf) {
+                match read_body(resp.into_reader(), &mut buf, &mut throttle) {
                     Ok(_) => {
                         // Write atomically-ish: create parent dirs if missing? (caller usually ensures)
                         // Use a temp file then rename to reduce partial writes on crash.
@@ -100,16 +439,18 @@ pub fn download_file(
                                 if let Err(e) = f.write_all(&buf) {
                                     let retry = retryable_io_error(e.kind());
                                     Err((
-                                        RetryKind::Io,
+                                        DownloadError::Io,
                                         format!("Failed to write temp file for {url}: {e}"),
                                         retry,
+                                        None,
                                     ))
                                 } else if let Err(e) = f.flush() {
                                     let retry = retryable_io_error(e.kind());
                                     Err((
-                                        RetryKind::Io,
+                                        DownloadError::Io,
                                         format!("Failed to flush temp file for {url}: {e}"),
                                         retry,
+                                        None,
                                     ))
                                 } else if let Err(e) = fs::rename(&tmp_path, path) {
                                     // On Windows rename may fail if target exists; try remove then rename.
@@ -120,9 +461,10 @@ pub fn download_file(
                                     match fs::rename(&tmp_path, path) {
                                         Ok(_) => Ok(()),
                                         Err(e2) => Err((
-                                            RetryKind::Io,
+                                            DownloadError::Io,
                                             format!("Failed to move temp file into place for {url}: {e2}"),
                                             retryable_io_error(e2.kind()) || retry,
+                                            None,
                                         )),
                                     }
                                 } else {
@@ -132,9 +474,10 @@ pub fn download_file(
                             Err(e) => {
                                 let retry = retryable_io_error(e.kind());
                                 Err((
-                                    RetryKind::Io,
+                                    DownloadError::Io,
                                     format!("Failed to create temp file for {url}: {e}"),
                                     retry,
+                                    None,
                                 ))
                             }
                         }
@@ -142,42 +485,51 @@ pub fn download_file(
                     Err(e) => {
                         // Treat read errors as transient
                         Err((
-                            RetryKind::Io,
+                            DownloadError::Io,
                             format!(
                                 "Failed to read response for {url} (attempt {attempt_no}/{retry_count}): {e}"
                             ),
                             true,
+                            None,
                         ))
                     }
                 }
             }
             Err(e) => match e {
-                ureq::Error::Status(code, _resp) => {
+                ureq::Error::Status(code, resp) => {
                     let code_u16 = code as u16;
                     if retryable_http_status(code_u16) {
+                        let retry_after = if matches!(code_u16, 429 | 503) {
+                            resp.header("Retry-After").and_then(|v| parse_retry_after(v, Utc::now()))
+                        } else {
+                            None
+                        };
                         Err((
-                            RetryKind::HttpStatus,
+                            DownloadError::HttpStatus(code_u16),
                             format!(
                                 "Server returned status {code_u16} for {url} (attempt {attempt_no}/{retry_count}), will retry"
                             ),
                             true,
+                            retry_after,
                         ))
                     } else {
                         Err((
-                            RetryKind::HttpStatus,
+                            DownloadError::HttpStatus(code_u16),
                             format!(
                                 "Non-retryable HTTP status {code_u16} for {url} (attempt {attempt_no}/{retry_count}), aborting"
                             ),
                             false,
+                            None,
                         ))
                     }
                 }
                 ureq::Error::Transport(err) => Err((
-                    RetryKind::Network,
+                    DownloadError::Network,
                     format!(
                         "Transport error downloading {url} (attempt {attempt_no}/{retry_count}): {err}"
                     ),
                     true,
+                    None,
                 )),
             },
         };
@@ -185,42 +537,55 @@ pub fn download_file(
         match outcome {
             Ok(()) => {
                 logger.log(&format!("Downloaded {url}"));
-                return true;
+                return Ok(());
             }
-            Err((kind, msg, should_retry)) => {
+            Err(_) if shutdown::requested() => return cancel(logger),
+            Err((kind, msg, should_retry, retry_after)) => {
                 logger.log(&msg);
+                last_error = kind;
 
-                if !should_retry {
-                    return false;
-                }
-
-                if attempt_no >= retry_count {
-                    break;
-                }
-
-                // Compute backoff
-                let backoff = compute_backoff_secs(base_delay, attempt as u32);
-                let capped = backoff.min(MAX_RETRY_SLEEP_SECS);
-
-                // As requested:
-                // - If backoff reaches/exceeds cap:
-                //   - Network errors: continue retrying (sleep stays capped).
-                //   - HTTP status errors: stop retrying once cap is reached/exceeded.
-                if backoff >= MAX_RETRY_SLEEP_SECS && kind == RetryKind::HttpStatus {
-                    logger.log(&format!(
-                        "Backoff reached cap ({}s) for HTTP status retries of {url}; stopping retries as configured",
-                        MAX_RETRY_SLEEP_SECS
-                    ));
-                    return false;
+                let failure = Failure { attempt, kind, retryable: should_retry, retry_after };
+                let time_left = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+                match policy.next_step(&failure, time_left) {
+                    Step::Retry { sleep, server_hint: Some(_) } => {
+                        logger.log(&format!(
+                            "Honoring server Retry-After for {url}, waiting {sleep}s (attempt {attempt_no}/{retry_count})"
+                        ));
+                        if !shutdown::sleep(Duration::from_secs(sleep)) {
+                            return cancel(logger);
+                        }
+                    }
+                    Step::Retry { sleep, server_hint: None } => {
+                        logger.log(&format!(
+                            "Waiting {sleep}s before next attempt for {url} (attempt {attempt_no}/{retry_count})"
+                        ));
+                        if !shutdown::sleep(Duration::from_secs(sleep)) {
+                            return cancel(logger);
+                        }
+                    }
+                    Step::Stop(StopReason::NotRetryable) => return Err(kind),
+                    Step::Stop(StopReason::Exhausted) => break,
+                    Step::Stop(StopReason::BackoffCap) => {
+                        logger.log(&format!(
+                            "Backoff reached cap ({MAX_RETRY_SLEEP_SECS}s) for HTTP status retries of {url}; stopping retries as configured"
+                        ));
+                        return Err(kind);
+                    }
+                    Step::Stop(StopReason::RetryAfterCap) => {
+                        logger.log(&format!(
+                            "Server asked to wait {}s for {url}, longer than max_retry_after ({}s); stopping retries",
+                            retry_after.unwrap_or_default(),
+                            policy.retry_after_cap
+                        ));
+                        return Err(kind);
+                    }
+                    Step::Stop(StopReason::TotalTimeout) => {
+                        logger.log(&format!(
+                            "Total timeout of {total_timeout_secs}s reached for {url}, giving up after attempt {attempt_no} ({kind})"
+                        ));
+                        return Err(DownloadError::Timeout);
+                    }
                 }
-
-                logger.log(&format!(
-                    "Waiting {}s before next attempt for {url} (attempt {}/{})",
-                    capped,
-                    attempt_no,
-                    retry_count
-                ));
-                thread::sleep(Duration::from_secs(capped));
             }
         }
     }
@@ -228,5 +593,102 @@ pub fn download_file(
     logger.log(&format!(
         "Failed to download {url} after {retry_count} attempts"
     ));
-    false
+    Err(last_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: RetryPolicy = RetryPolicy { retry_count: 10, base_delay: 2, retry_after_cap: 60 };
+
+    fn failure(attempt: u32, kind: DownloadError, retry_after: Option<u64>) -> Failure {
+        let retryable = match kind {
+            DownloadError::HttpStatus(code) => retryable_http_status(code),
+            _ => true,
+        };
+        Failure { attempt, kind, retryable, retry_after }
+    }
+
+    #[test]
+    fn server_error_retries_with_backoff() {
+        let step = |attempt| POLICY.next_step(&failure(attempt, DownloadError::HttpStatus(503), None), None);
+        assert_eq!(step(0), Step::Retry { sleep: 2, server_hint: None });
+        assert_eq!(step(1), Step::Retry { sleep: 4, server_hint: None });
+        assert_eq!(step(3), Step::Retry { sleep: 16, server_hint: None });
+    }
+
+    #[test]
+    fn not_found_stops_immediately() {
+        let step = POLICY.next_step(&failure(0, DownloadError::HttpStatus(404), None), None);
+        assert_eq!(step, Step::Stop(StopReason::NotRetryable));
+    }
+
+    #[test]
+    fn rate_limit_retry_after_above_cap_stops() {
+        let within = POLICY.next_step(&failure(0, DownloadError::HttpStatus(429), Some(30)), None);
+        assert_eq!(within, Step::Retry { sleep: 30, server_hint: Some(30) });
+        let beyond = POLICY.next_step(&failure(0, DownloadError::HttpStatus(429), Some(120)), None);
+        assert_eq!(beyond, Step::Stop(StopReason::RetryAfterCap));
+    }
+
+    #[test]
+    fn network_error_keeps_retrying_past_cap() {
+        // 2 * 2^6 = 128s, past the 60s cap.
+        let network = POLICY.next_step(&failure(6, DownloadError::Network, None), None);
+        assert_eq!(network, Step::Retry { sleep: MAX_RETRY_SLEEP_SECS, server_hint: None });
+        let status = POLICY.next_step(&failure(6, DownloadError::HttpStatus(503), None), None);
+        assert_eq!(status, Step::Stop(StopReason::BackoffCap));
+    }
+
+    #[test]
+    fn last_attempt_is_exhausted() {
+        let policy = RetryPolicy { retry_count: 3, ..POLICY };
+        assert_eq!(
+            policy.next_step(&failure(1, DownloadError::Network, None), None),
+            Step::Retry { sleep: 4, server_hint: None }
+        );
+        assert_eq!(policy.next_step(&failure(2, DownloadError::Network, None), None), Step::Stop(StopReason::Exhausted));
+    }
+
+    #[test]
+    fn spent_deadline_skips_later_downloads() {
+        let config = Config { total_timeout_secs: 5, ..Default::default() };
+        let options = DownloadOptions { deadline: Some(Instant::now()), ..DownloadOptions::from_config(&config) };
+        let dir = std::env::temp_dir();
+        let log = dir.join(format!("auto-wallpaper-deadline-{}.log", std::process::id()));
+        let mut logger = Logger::new(&log);
+        // Unroutable, so only the deadline check keeps this from trying.
+        let result = download_file("http://192.0.2.1/tile.png", &dir.join("tile.png"), &mut logger, &options);
+        assert_eq!(result, Err(DownloadError::Timeout));
+        let logged = fs::read_to_string(&log).unwrap_or_default();
+        let _ = fs::remove_file(&log);
+        assert!(logged.contains("Total timeout of 5s already used up"), "{logged}");
+    }
+
+    #[test]
+    fn retry_past_deadline_is_a_timeout() {
+        // A local server that is always busy.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/api.json", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request);
+                let _ = stream.write_all(b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+            }
+        });
+
+        // The first backoff (2s) would outlast the 1s budget.
+        let config = Config { total_timeout_secs: 1, retry_delay: 2, ..Default::default() };
+        let options = DownloadOptions::from_config(&config);
+        let dir = std::env::temp_dir();
+        let log = dir.join(format!("auto-wallpaper-retry-deadline-{}.log", std::process::id()));
+        let mut logger = Logger::new(&log);
+        let result = download_file(&url, &dir.join("busy.json"), &mut logger, &options);
+        let logged = fs::read_to_string(&log).unwrap_or_default();
+        let _ = fs::remove_file(&log);
+        assert_eq!(result, Err(DownloadError::Timeout), "{logged}");
+        assert!(logged.contains("Total timeout of 1s reached"), "{logged}");
+    }
 }
@@ -0,0 +1,100 @@
+use std::fs;
+use std::path::Path;
+
+use chrono::NaiveDateTime;
+use image::imageops;
+use image::{DynamicImage, Rgba, RgbaImage};
+use serde_json::Value;
+
+use crate::download::{download_file, DownloadOptions};
+use crate::logger::Logger;
+use crate::metadata::Metadata;
+use crate::postprocess::save_jpeg;
+
+const HIMAWARI_BASE: &str = "https://himawari8-dl.nict.go.jp/himawari8/img/D531106";
+
+/// Edge length of each served tile in pixels.
+const TILE_SIZE: u32 = 550;
+
+/// Read the timestamp of the newest full-disk image from `latest.json`.
+fn latest_timestamp(latest_json: &Path) -> Option<NaiveDateTime> {
+    let date = fs::read_to_string(latest_json)
+        .ok()
+        .and_then(|s| serde_json::from_str::<Value>(&s).ok())
+        .and_then(|v| v["date"].as_str().map(String::from))?;
+    NaiveDateTime::parse_from_str(&date, "%Y-%m-%d %H:%M:%S").ok()
+}
+
+/// Download the latest Himawari-8 full-disk image as `level×level` tiles and
+/// stitch them into `image_path`. Missing tiles are left black.
+pub fn download_latest(
+    dfolder: &Path,
+    image_path: &Path,
+    level: u32,
+    options: &DownloadOptions,
+    logger: &mut Logger,
+) -> Option<Metadata> {
+    let latest_json = dfolder.join("himawari_latest.json");
+    if let Err(e) = download_file(&format!("{HIMAWARI_BASE}/latest.json"), &latest_json, logger, options) {
+        logger.log(&format!("Failed to download Himawari latest.json: {e}"));
+        return None;
+    }
+    let timestamp = match latest_timestamp(&latest_json) {
+        Some(t) => t,
+        None => {
+            logger.log("Failed to parse timestamp from Himawari latest.json");
+            return None;
+        }
+    };
+    let stamp_path = timestamp.format("%Y/%m/%d/%H%M%S").to_string();
+    let tile_base = format!("{HIMAWARI_BASE}/{level}d/{TILE_SIZE}/{stamp_path}");
+    logger.log(&format!("Himawari image {timestamp} at level {level} ({} tiles)", level * level));
+
+    let tile_dir = dfolder.join("tiles");
+    let _ = fs::create_dir_all(&tile_dir);
+
+    let side = TILE_SIZE * level;
+    let mut canvas = RgbaImage::from_pixel(side, side, Rgba([0, 0, 0, 255]));
+    let mut missing = 0u32;
+    for y in 0..level {
+        for x in 0..level {
+            let tile_path = tile_dir.join(format!("{x}_{y}.png"));
+            let ok = download_file(&format!("{tile_base}_{x}_{y}.png"), &tile_path, logger, options).is_ok();
+            match ok.then(|| image::open(&tile_path).ok()).flatten() {
+                Some(tile) => {
+                    imageops::overlay(&mut canvas, &tile.to_rgba8(), (x * TILE_SIZE) as i64, (y * TILE_SIZE) as i64);
+                }
+                None => {
+                    logger.log(&format!("Himawari tile ({x}, {y}) unavailable, leaving it black"));
+                    missing += 1;
+                }
+            }
+        }
+    }
+    let _ = fs::remove_dir_all(&tile_dir);
+
+    if missing == level * level {
+        logger.log("No Himawari tiles could be downloaded");
+        return None;
+    }
+    if missing > 0 {
+        logger.log(&format!("Himawari image stitched with {missing} missing tiles"));
+    }
+
+    if let Err(e) = save_jpeg(DynamicImage::ImageRgba8(canvas), image_path) {
+        logger.log(&format!("Failed to save stitched Himawari image: {e}"));
+        return None;
+    }
+
+    let day = timestamp.format("%Y%m%d").to_string();
+    Some(Metadata {
+        urlbase: format!("/{level}d/{TILE_SIZE}/{stamp_path}"),
+        url: tile_base,
+        title: format!("Himawari-8 full disk {timestamp} UTC"),
+        copyright: "NICT / JMA".into(),
+        startdate: day.clone(),
+        enddate: day,
+        market: String::new(),
+        duplicate_of: None,
+    })
+}
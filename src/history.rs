@@ -0,0 +1,134 @@
+use std::fs;
+use std::path::Path;
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::archive::dated_folders;
+use crate::config::Config;
+use crate::logger::Logger;
+use crate::metadata::load_metadata;
+use crate::{load_status, Error};
+
+/// Run history in the data folder, one record per run.
+pub(crate) const HISTORY_FILE: &str = "history.json";
+
+/// One run, or one day backfilled from its folder's `status.json`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct HistoryEntry {
+    /// Day folder name.
+    date: String,
+    /// When the record was written; completion or download time when backfilled.
+    #[serde(default)]
+    recorded: Option<String>,
+    /// Configured source; unknown for backfilled days.
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default)]
+    market: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    completed: bool,
+    #[serde(default)]
+    downloaded: bool,
+    #[serde(default)]
+    watermark_added: bool,
+    #[serde(default)]
+    wallpaper_set: bool,
+    #[serde(default)]
+    download_time: Option<String>,
+    #[serde(default)]
+    completed_time: Option<String>,
+    /// Why the run failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Snapshot of a day folder's status and metadata.
+fn day_entry(dfolder: &Path, name: &str) -> HistoryEntry {
+    let status = load_status(&dfolder.join("status.json"));
+    let metadata = load_metadata(&dfolder.join("metadata.json")).unwrap_or_default();
+    HistoryEntry {
+        date: name.to_string(),
+        recorded: status.completed_time.clone().or_else(|| status.download_time.clone()),
+        source: None,
+        market: metadata.market,
+        title: metadata.title,
+        completed: status.completed,
+        downloaded: status.downloaded,
+        watermark_added: status.watermark_added,
+        wallpaper_set: status.wallpaper_set,
+        download_time: status.download_time,
+        completed_time: status.completed_time,
+        error: None,
+    }
+}
+
+/// `None` when the file is missing or unreadable.
+pub(crate) fn load_history(path: &Path) -> Option<Vec<HistoryEntry>> {
+    fs::read_to_string(path).ok().and_then(|s| serde_json::from_str(&s).ok())
+}
+
+/// Entries for every day folder with a `status.json`, live and archived,
+/// oldest first. `skip` (today) is left to the run's own record.
+fn backfill(folder: &Path, skip: &str, date_format: &str, logger: &mut Logger) -> Vec<HistoryEntry> {
+    let mut days = dated_folders(folder, date_format);
+    // Archived days live in per-year subfolders.
+    if let Ok(years) = fs::read_dir(folder.join("Archive")) {
+        for year in years.flatten() {
+            days.extend(dated_folders(&year.path(), date_format));
+        }
+    }
+    days.sort();
+    let entries: Vec<HistoryEntry> = days
+        .iter()
+        .filter(|(_, dir)| dir.join("status.json").exists())
+        .filter_map(|(_, dir)| {
+            let name = dir.file_name()?.to_string_lossy().into_owned();
+            (name != skip).then(|| day_entry(dir, &name))
+        })
+        .collect();
+    logger.log(&format!("History backfilled with {} days from status.json files", entries.len()));
+    entries
+}
+
+/// Append this run's outcome for day `name` to `history.json`, creating it
+/// from the existing day folders on first use.
+pub(crate) fn record(folder: &Path, name: &str, config: &Config, error: Option<&Error>, logger: &mut Logger) {
+    let path = folder.join(HISTORY_FILE);
+    let mut entries = match load_history(&path) {
+        Some(entries) => entries,
+        None => {
+            if path.exists() {
+                let bak = path.with_extension("json.bak");
+                logger.log(&format!("{} is unreadable, moved to {}", path.display(), bak.display()));
+                let _ = fs::rename(&path, &bak);
+            }
+            backfill(folder, name, &config.date_format, logger)
+        }
+    };
+
+    entries.push(HistoryEntry {
+        recorded: Some(Local::now().to_rfc3339()),
+        source: Some(config.source.clone()),
+        error: error.map(|e| e.to_string()),
+        ..day_entry(&folder.join(name), name)
+    });
+
+    let json = match serde_json::to_string_pretty(&entries) {
+        Ok(json) => json,
+        Err(e) => {
+            logger.log(&format!("Failed to serialize history: {e}"));
+            return;
+        }
+    };
+    // Same temp-file-and-rename as status.json.
+    let tmp = path.with_extension("tmp");
+    if let Err(e) = fs::write(&tmp, json).and_then(|_| fs::rename(&tmp, &path)) {
+        let _ = fs::remove_file(&tmp);
+        logger.log(&format!("Failed to write {}: {e}", path.display()));
+        return;
+    }
+    logger.log(&format!("Run recorded in {HISTORY_FILE} ({} entries)", entries.len()));
+}
@@ -0,0 +1,36 @@
+use std::path::Path;
+
+use image::imageops::FilterType;
+use image::DynamicImage;
+
+/// Maximum Hamming distance at which two dHashes are considered the same picture.
+pub const SIMILARITY_THRESHOLD: u32 = 5;
+
+/// 64-bit difference hash: downscale to 9×8 grayscale and compare neighbours.
+pub fn dhash(img: &DynamicImage) -> u64 {
+    let small = img.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let left = small.get_pixel(x, y)[0];
+            let right = small.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | u64::from(left > right);
+        }
+    }
+    hash
+}
+
+/// Decode an image file and compute its dHash.
+pub fn file_dhash(path: &Path) -> Option<u64> {
+    image::open(path).ok().map(|img| dhash(&img))
+}
+
+#[inline]
+pub fn hamming(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Whether two hashes describe visually identical images.
+pub fn is_similar(a: u64, b: u64) -> bool {
+    hamming(a, b) <= SIMILARITY_THRESHOLD
+}
@@ -0,0 +1,1310 @@
+//! Download, post-process and set the daily wallpaper.
+//!
+//! [`run_once`] performs one complete run, the same as launching the binary;
+//! [`download_file`], [`add_watermarks`] and [`set_wallpaper`] are the
+//! individual steps for callers that drive the pipeline themselves.
+
+mod archive;
+pub mod commands;
+pub mod config;
+mod disk;
+pub mod download;
+mod display;
+mod himawari;
+mod history;
+mod image_hash;
+mod local;
+mod lock;
+pub mod logger;
+pub mod metadata;
+mod postprocess;
+pub mod source;
+pub mod shutdown;
+pub mod update;
+pub mod wallpaper;
+pub mod watermark;
+
+use std::env;
+use std::fs;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use chrono::{Datelike, Local, NaiveDate};
+use image::GenericImageView;
+use serde::{Deserialize, Serialize};
+
+use archive::{archive_old_folders, dated_folders, parse_day_name};
+use config::{read_data_dir, read_date_format, valid_date_format, ARCHIVE_DAYS, DEFAULT_DATE_FORMAT};
+use download::{DownloadError, DownloadOptions};
+use metadata::{load_metadata, save_metadata, Metadata};
+use postprocess::{apply_adjustments, crop_to_aspect};
+use source::{ImageSource, SourceContext};
+use update::sha256_hex;
+use wallpaper::{fade_transition, get_current_wallpaper, set_lock_screen, WallpaperError};
+use watermark::add_caption_bar;
+
+pub use config::Config;
+pub use download::download_file;
+pub use logger::Logger;
+pub use wallpaper::set_wallpaper;
+pub use watermark::add_watermarks;
+
+const BING_API: &str = "https://www.bing.com/HPImageArchive.aspx?n=1";
+/// Re-checks of the API while waiting for today's image (`fallback_to_previous`).
+const STALE_RETRY_COUNT: u32 = 3;
+const STALE_RETRY_DELAY_SECS: u64 = 60;
+
+// ── Status tracking ──────────────────────────────────────────────────────────
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Status {
+    #[serde(default)]
+    completed: bool,
+    #[serde(default)]
+    downloaded: bool,
+    #[serde(default)]
+    cropped: bool,
+    #[serde(default)]
+    watermark_added: bool,
+    #[serde(default)]
+    adjusted: bool,
+    #[serde(default)]
+    captioned: bool,
+    #[serde(default)]
+    wallpaper_set: bool,
+    #[serde(default)]
+    completed_time: Option<String>,
+    #[serde(default)]
+    download_time: Option<String>,
+    /// `Config::output_hash` of the settings today's image was produced with.
+    #[serde(default)]
+    settings_hash: Option<String>,
+    /// Hex dHash of the image last set as wallpaper.
+    #[serde(default)]
+    wallpaper_hash: Option<String>,
+    /// Image set as wallpaper when it isn't today's (`pick_from_recent_days`).
+    #[serde(default)]
+    applied_image: Option<String>,
+    /// File name of the day's image, resolved from `filename_pattern`.
+    #[serde(default)]
+    image_file: Option<String>,
+}
+
+pub(crate) fn load_status(path: &Path) -> Status {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+/// Write through a temp file so an interrupted run never leaves a truncated status.
+fn save_status(path: &Path, status: &Status) {
+    if let Ok(json) = serde_json::to_string_pretty(status) {
+        let tmp = path.with_extension("tmp");
+        if fs::write(&tmp, json).is_ok() && fs::rename(&tmp, path).is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+    }
+}
+
+// ── Helpers ──────────────────────────────────────────────────────────────────
+
+/// Root data folder: `AW_DATA_DIR`, else the config's `data_dir`, else
+/// `%APPDATA%\AutoWallpaper`. `portable` means a `data` folder next to the exe;
+/// relative paths are resolved against the exe directory.
+pub fn data_folder() -> PathBuf {
+    let base_path = get_base_path();
+    let configured = env::var("AW_DATA_DIR")
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .or_else(|| read_data_dir(&base_path.join("config.json")));
+    match configured.as_deref() {
+        Some(dir) if dir.eq_ignore_ascii_case("portable") => base_path.join("data"),
+        Some(dir) => {
+            let path = PathBuf::from(expand_env(dir));
+            if path.is_absolute() { path } else { base_path.join(path) }
+        }
+        None => {
+            let appdata = env::var("APPDATA").unwrap_or_default();
+            PathBuf::from(&appdata).join("AutoWallpaper")
+        }
+    }
+}
+
+/// The name of `date`'s folder (and log and `{date}` token) in `date_format`,
+/// e.g. `2026.02.15`. An unusable format falls back to the default.
+pub fn date_name(date: NaiveDate, date_format: &str) -> String {
+    let format = if valid_date_format(date_format) { date_format } else { DEFAULT_DATE_FORMAT };
+    date.format(format).to_string()
+}
+
+/// Today's folder name, using the `date_format` from the config file.
+pub fn today_name() -> String {
+    date_name(Local::now().date_naive(), &read_date_format(&get_base_path().join("config.json")))
+}
+
+/// How much of an unparseable API response to quote in the log.
+const API_SNIPPET_LEN: usize = 200;
+
+/// Why a Bing API response yielded no usable image.
+enum ApiResponseError {
+    /// Well-formed response with an empty `images` array.
+    NoImages,
+    /// Unreadable, non-JSON or missing `urlbase`; carries a truncated snippet.
+    Malformed(String),
+}
+
+/// Parse a saved Bing API response into image metadata.
+fn read_api_response(api_json: &Path, market: &str) -> Result<Metadata, ApiResponseError> {
+    let text = fs::read_to_string(api_json).map_err(|e| ApiResponseError::Malformed(e.to_string()))?;
+    let snippet = || {
+        let mut s: String = text.chars().take(API_SNIPPET_LEN).collect();
+        if text.chars().count() > API_SNIPPET_LEN {
+            s.push_str("...");
+        }
+        ApiResponseError::Malformed(s)
+    };
+    let v = serde_json::from_str::<serde_json::Value>(&text).map_err(|_| snippet())?;
+    if v["images"].as_array().is_some_and(|a| a.is_empty()) {
+        return Err(ApiResponseError::NoImages);
+    }
+    Metadata::from_api(&v, market).ok_or_else(snippet)
+}
+
+pub(crate) fn parse_api_response(api_json: &Path, market: &str) -> Option<Metadata> {
+    read_api_response(api_json, market).ok()
+}
+
+/// Query the Bing API for each configured market in turn, returning the
+/// metadata of the first one that yields a usable image.
+fn fetch_metadata(
+    config: &Config,
+    api_json: &Path,
+    download_options: &DownloadOptions,
+    logger: &mut Logger,
+) -> Option<Metadata> {
+    let markets = config.markets();
+    for (i, mkt) in markets.iter().enumerate() {
+        let api_url = format!("{BING_API}&mkt={mkt}&idx={}&format=js", config.idx);
+        match download_file(&api_url, api_json, logger, download_options) {
+            Ok(()) => {}
+            // Every market is served by the same host, so a network failure
+            // that outlasted the retries won't be cured by the next market,
+            // and a spent total timeout covers them all.
+            Err(
+                e @ (DownloadError::Disabled
+                | DownloadError::Network
+                | DownloadError::Cancelled
+                | DownloadError::Timeout),
+            ) => {
+                logger.log(&format!("Failed to download API files for market {mkt}: {e}, giving up"));
+                return None;
+            }
+            Err(e) => {
+                logger.log(&format!("Failed to download API files for market {mkt}: {e}"));
+                continue;
+            }
+        }
+        match read_api_response(api_json, mkt) {
+            Ok(m) => {
+                if i > 0 {
+                    logger.log(&format!("Using fallback market {mkt}"));
+                } else if markets.len() > 1 {
+                    logger.log(&format!("Using market {mkt}"));
+                }
+                check_image_date(&m, config.idx, logger);
+                if config.fallback_to_previous && image_is_stale(&m, config.idx) {
+                    return wait_for_fresh_image(m, &api_url, api_json, download_options, config.idx, logger);
+                }
+                return Some(m);
+            }
+            Err(ApiResponseError::NoImages) => logger.log(&format!("Bing returned no images for market {mkt}")),
+            Err(ApiResponseError::Malformed(snippet)) => logger.log(&format!(
+                "Failed to parse download link from API response for market {mkt}: {snippet}"
+            )),
+        }
+    }
+    None
+}
+
+/// Date the API response should be for: `idx` days before today.
+fn expected_image_date(idx: u8) -> NaiveDate {
+    Local::now().date_naive() - chrono::Duration::days(idx as i64)
+}
+
+/// Whether the API returned an older image than `idx` asks for, which
+/// happens shortly after midnight before Bing has published the new one.
+fn image_is_stale(metadata: &Metadata, idx: u8) -> bool {
+    NaiveDate::parse_from_str(&metadata.startdate, "%Y%m%d").is_ok_and(|start| start < expected_image_date(idx))
+}
+
+/// Re-query the API a bounded number of times waiting for today's image,
+/// then settle for the previous day's. Stops waiting early when the wait
+/// would run past the total timeout; `None` on a shutdown request.
+fn wait_for_fresh_image(
+    stale: Metadata,
+    api_url: &str,
+    api_json: &Path,
+    download_options: &DownloadOptions,
+    idx: u8,
+    logger: &mut Logger,
+) -> Option<Metadata> {
+    let market = stale.market.clone();
+    let delay = Duration::from_secs(STALE_RETRY_DELAY_SECS);
+    for attempt in 1..=STALE_RETRY_COUNT {
+        if download_options.deadline.is_some_and(|d| d.saturating_duration_since(Instant::now()) <= delay) {
+            logger.log(&format!(
+                "Total timeout of {}s leaves no time to wait for today's image",
+                download_options.total_timeout_secs
+            ));
+            break;
+        }
+        logger.log(&format!(
+            "Today's image is not available yet for market {market}, checking again in {STALE_RETRY_DELAY_SECS}s ({attempt}/{STALE_RETRY_COUNT})"
+        ));
+        if !shutdown::sleep(delay) {
+            return None;
+        }
+        match download_file(api_url, api_json, logger, download_options) {
+            Ok(()) => {}
+            Err(DownloadError::Cancelled) => return None,
+            Err(DownloadError::Timeout) => break,
+            Err(_) => continue,
+        }
+        if let Ok(fresh) = read_api_response(api_json, &market) {
+            if !image_is_stale(&fresh, idx) {
+                logger.log(&format!("Today's image is now available (startdate={})", fresh.startdate));
+                return Some(fresh);
+            }
+        }
+    }
+    logger.log(&format!(
+        "Today's image is still not available for market {market}, falling back to the previous image (startdate={})",
+        stale.startdate
+    ));
+    Some(stale)
+}
+
+/// Log which day the API actually returned and warn when it isn't the day
+/// `idx` asks for (`idx` = days before today).
+fn check_image_date(metadata: &Metadata, idx: u8, logger: &mut Logger) {
+    logger.log(&format!(
+        "Bing image startdate={}, enddate={} (idx={idx})",
+        metadata.startdate, metadata.enddate
+    ));
+    let expected = expected_image_date(idx);
+    match NaiveDate::parse_from_str(&metadata.startdate, "%Y%m%d") {
+        Ok(start) if start != expected => logger.log(&format!(
+            "Warning: idx={idx} expected the image for {}, but Bing returned {}",
+            expected.format("%Y-%m-%d"),
+            start.format("%Y-%m-%d")
+        )),
+        Ok(_) => {}
+        Err(_) => logger.log("Warning: API response has no valid startdate"),
+    }
+}
+
+/// Find the most recent date-named folder before `today` under `folder`.
+fn previous_day_folder(folder: &Path, today: &str, date_format: &str) -> Option<(String, PathBuf)> {
+    let today = parse_day_name(today, date_format)?;
+    dated_folders(folder, date_format)
+        .into_iter()
+        .filter(|(date, _)| *date < today)
+        .max_by_key(|(date, _)| *date)
+        .map(|(_, path)| (folder_name(&path), path))
+}
+
+fn folder_name(path: &Path) -> String {
+    path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// A day's image as recorded in its `status.json`; folders written before
+/// `filename_pattern` existed hold `<date>.jpg`.
+pub(crate) fn day_image(folder: &Path, name: &str) -> PathBuf {
+    let file = load_status(&folder.join("status.json")).image_file;
+    folder.join(file.unwrap_or_else(|| format!("{name}.jpg")))
+}
+
+/// The unprocessed copy kept beside `image`: `photo.jpg` -> `photo_original.jpg`.
+pub(crate) fn original_path(image: &Path) -> PathBuf {
+    let stem = image.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    image.with_file_name(format!("{stem}_original.jpg"))
+}
+
+/// SHA-256 of `image` right after watermarking: `photo.jpg` -> `photo.wm.sha256`.
+/// Kept out of `status.json` so a lost status file can't lead to a second stamp.
+fn watermark_hash_path(image: &Path) -> PathBuf {
+    image.with_extension("wm.sha256")
+}
+
+/// Whether `image` is byte-for-byte the file last written by watermarking.
+fn is_watermarked_output(image: &Path) -> bool {
+    let stored = fs::read_to_string(watermark_hash_path(image)).unwrap_or_default();
+    !stored.trim().is_empty() && sha256_hex(image).is_some_and(|h| h == stored.trim())
+}
+
+/// Lowercase alphanumeric words of `title` joined by `-`, at most 60 characters.
+fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+    for word in title.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+        if slug.chars().count() + word.chars().count() >= 60 {
+            break;
+        }
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.extend(word.chars().flat_map(char::to_lowercase));
+    }
+    slug
+}
+
+/// Expand `filename_pattern` for today's image. `{title-slug}` and the actual
+/// market are only known once `metadata` exists; before that they resolve to
+/// an empty string and the configured market.
+fn resolve_filename(config: &Config, name: &str, metadata: Option<&Metadata>) -> String {
+    let market = metadata
+        .map(|m| m.market.as_str())
+        .filter(|m| !m.is_empty())
+        .unwrap_or(&config.mkt);
+    let title = metadata.map(|m| slugify(&m.title)).unwrap_or_default();
+    let file = config
+        .filename_pattern
+        .replace("{date}", name)
+        .replace("{market}", market)
+        .replace("{idx}", &config.idx.to_string())
+        .replace("{title-slug}", &title);
+    let stem = Path::new(&file).file_stem().map(|s| s.to_string_lossy().trim().to_string()).unwrap_or_default();
+    if stem.is_empty() {
+        return format!("{name}.jpg");
+    }
+    let is_jpeg = Path::new(&file)
+        .extension()
+        .is_some_and(|x| x.eq_ignore_ascii_case("jpg") || x.eq_ignore_ascii_case("jpeg"));
+    if is_jpeg { file } else { format!("{file}.jpg") }
+}
+
+/// Rename a day's image (and its original, if any) when its resolved name changes.
+fn rename_image(from: &Path, to: &Path, logger: &mut Logger) -> bool {
+    if let Err(e) = fs::rename(from, to) {
+        logger.log(&format!("Failed to rename {} to {}: {e}", from.display(), to.display()));
+        return false;
+    }
+    let original = original_path(from);
+    if original.exists() {
+        let _ = fs::rename(&original, original_path(to));
+    }
+    let wm_hash = watermark_hash_path(from);
+    if wm_hash.exists() {
+        let _ = fs::rename(&wm_hash, watermark_hash_path(to));
+    }
+    logger.log(&format!("Renamed {} to {}", from.display(), to.display()));
+    true
+}
+
+/// Pick a random day among the last `days` dated folders (today included)
+/// whose image verifies. Returns the folder name and image path.
+fn pick_recent_image(
+    folder: &Path,
+    today: &str,
+    days: u32,
+    date_format: &str,
+    min_dims: (u32, u32),
+    logger: &mut Logger,
+) -> Option<(String, PathBuf)> {
+    let today = parse_day_name(today, date_format)?;
+    let oldest = today - chrono::Duration::days(days.saturating_sub(1) as i64);
+    let mut candidates: Vec<(String, PathBuf)> = dated_folders(folder, date_format)
+        .into_iter()
+        .filter(|(date, _)| (oldest..=today).contains(date))
+        .map(|(_, path)| {
+            let name = folder_name(&path);
+            let image = day_image(&path, &name);
+            (name, image)
+        })
+        .collect();
+    candidates.retain(|(_, image)| image.exists() && verify_image(image, min_dims, logger));
+    if candidates.is_empty() {
+        return None;
+    }
+    Some(candidates.swap_remove(local::random_index(candidates.len())))
+}
+
+/// Copy the previous day's image into place when its `urlbase` matches today's.
+/// Prefers the un-watermarked original so today's watermarks aren't stacked.
+fn reuse_previous_image(
+    prev_name: &str,
+    prev_folder: &Path,
+    urlbase: &str,
+    image_path: &Path,
+    min_dims: (u32, u32),
+    logger: &mut Logger,
+) -> bool {
+    let same = load_metadata(&prev_folder.join("metadata.json"))
+        .is_some_and(|m| !m.urlbase.is_empty() && m.urlbase == urlbase);
+    if !same {
+        return false;
+    }
+
+    let prev_image = day_image(prev_folder, prev_name);
+    let original = original_path(&prev_image);
+    let source = if original.exists() { original } else { prev_image };
+    if !verify_image(&source, min_dims, logger) {
+        return false;
+    }
+
+    match fs::copy(&source, image_path) {
+        Ok(_) => {
+            logger.log(&format!("Image is a duplicate of {prev_name}, copied instead of re-downloading"));
+            true
+        }
+        Err(e) => {
+            logger.log(&format!("Failed to copy duplicate image from {prev_name}: {e}"));
+            false
+        }
+    }
+}
+
+/// Compare a freshly downloaded image with the previous day's by perceptual hash.
+fn detect_duplicate_content(prev_name: &str, prev_folder: &Path, image_path: &Path) -> bool {
+    let prev_image = day_image(prev_folder, prev_name);
+    let original = original_path(&prev_image);
+    let prev_image = if original.exists() { original } else { prev_image };
+    match (image_hash::file_dhash(image_path), image_hash::file_dhash(&prev_image)) {
+        (Some(a), Some(b)) => image_hash::is_similar(a, b),
+        _ => false,
+    }
+}
+
+pub fn get_base_path() -> PathBuf {
+    env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(|d| d.to_path_buf()))
+        .unwrap_or_else(|| env::current_dir().unwrap_or_default())
+}
+
+/// Decoded size and file size of a verified image, for log lines.
+struct ImageInfo {
+    width: u32,
+    height: u32,
+    bytes: u64,
+}
+
+impl fmt::Display for ImageInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}, {:.2} MB", self.width, self.height, self.bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+/// Verify that an image file exists, is large enough, can be decoded, and
+/// meets the minimum `(width, height)` in pixels.
+pub(crate) fn verify_image(path: &Path, min_dims: (u32, u32), logger: &mut Logger) -> bool {
+    inspect_image(path, min_dims, logger).is_some()
+}
+
+/// [`verify_image`], returning the decoded dimensions and byte size on success.
+fn inspect_image(path: &Path, min_dims: (u32, u32), logger: &mut Logger) -> Option<ImageInfo> {
+    let meta = fs::metadata(path).ok()?;
+    if meta.len() < 10 * 1024 {
+        logger.log(&format!(
+            "Image file too small ({} bytes): {}",
+            meta.len(),
+            path.display()
+        ));
+        return None;
+    }
+    match image::open(path) {
+        Ok(img) => {
+            let (w, h) = img.dimensions();
+            if w < min_dims.0 || h < min_dims.1 {
+                logger.log(&format!(
+                    "Image dimensions {w}x{h} below minimum {}x{}: {}",
+                    min_dims.0,
+                    min_dims.1,
+                    path.display()
+                ));
+                return None;
+            }
+            Some(ImageInfo { width: w, height: h, bytes: meta.len() })
+        }
+        Err(e) => {
+            logger.log(&format!("Image verification failed: {e}"));
+            None
+        }
+    }
+}
+
+/// Check whether today's wallpaper has already been successfully applied.
+fn check_already_completed(
+    dfolder: &Path,
+    image_path: &Path,
+    min_dims: (u32, u32),
+    settings_hash: &str,
+    logger: &mut Logger,
+) -> bool {
+    let status_file = dfolder.join("status.json");
+
+    let mut status = load_status(&status_file);
+
+    if !status.completed {
+        return false;
+    }
+    if !verify_image(image_path, min_dims, logger) {
+        logger.log("Previous image file is missing or corrupted, will re-download");
+        return false;
+    }
+
+    // Status files written before the hash existed are treated as up to date.
+    if status.settings_hash.as_deref().is_some_and(|h| h != settings_hash) {
+        logger.log("Image processing settings changed since last run, will re-process");
+        status.completed = false;
+        save_status(&status_file, &status);
+        return false;
+    }
+
+    let applied_path = status.applied_image.as_ref().map_or_else(|| image_path.to_path_buf(), PathBuf::from);
+    if let Some(current) = get_current_wallpaper() {
+        let current_norm = normalize_path(&current);
+        let abs = fs::canonicalize(&applied_path).unwrap_or_else(|_| applied_path.clone());
+        let abs_str = abs.to_string_lossy();
+        let clean = abs_str.strip_prefix(r"\\?\").unwrap_or(&abs_str);
+        let target_norm = normalize_path(clean);
+
+        if current_norm != target_norm {
+            logger.log("Current wallpaper differs from today's image, will re-apply");
+            status.wallpaper_set = false;
+            save_status(&status_file, &status);
+            return false;
+        }
+    }
+
+    logger.log("Today's wallpaper already completed and verified");
+    true
+}
+
+/// Whether the desktop already shows a picture visually identical to
+/// `image_path` under another path, so setting it again would only flicker.
+/// The current file is hashed when readable; when the current wallpaper path
+/// can't be read, the hash recorded at the last successful set is used.
+fn showing_identical_image(
+    image_path: &Path,
+    target_hash: u64,
+    last_set_hash: Option<&str>,
+    logger: &mut Logger,
+) -> bool {
+    let current = get_current_wallpaper();
+    let current_hash = match &current {
+        Some(current) => {
+            let abs = fs::canonicalize(image_path).unwrap_or_else(|_| image_path.to_path_buf());
+            if normalize_path(current) == normalize_path(&abs.to_string_lossy()) {
+                return false;
+            }
+            image_hash::file_dhash(Path::new(current))
+        }
+        None => last_set_hash.and_then(|h| u64::from_str_radix(h, 16).ok()),
+    };
+    let Some(current_hash) = current_hash else {
+        return false;
+    };
+    if !image_hash::is_similar(target_hash, current_hash) {
+        return false;
+    }
+    match current {
+        Some(current) => logger.log(&format!("Current wallpaper {current} is visually identical to today's image, not re-setting")),
+        None => logger.log("Current wallpaper path unknown but last set image is visually identical, not re-setting"),
+    }
+    true
+}
+
+fn normalize_path(path: &str) -> String {
+    wallpaper::normalize_path(path)
+}
+
+fn copy_to_desktop(image_path: &Path, logger: &mut Logger) {
+    if let Ok(home) = env::var("USERPROFILE") {
+        let dest = PathBuf::from(home).join("Desktop").join("wallpaper.jpg");
+        match fs::copy(image_path, &dest) {
+            Ok(_) => logger.log("Wallpaper copied to desktop"),
+            Err(e) => logger.log(&format!("Failed to copy wallpaper to desktop: {e}")),
+        }
+    }
+}
+
+/// Expand `%VAR%` style environment variables in a string.
+/// `%%` is collapsed to a literal `%`.
+fn expand_env(s: &str) -> String {
+    let mut result = s.to_string();
+    let mut idx = 0;
+    while let Some(start) = result[idx..].find('%') {
+        let start = start + idx;
+        if let Some(end) = result[start + 1..].find('%') {
+            let var = &result[start + 1..start + 1 + end];
+            if var.is_empty() {
+                // %% → %
+                result = format!("{}%{}", &result[..start], &result[start + 2..]);
+                idx = start + 1;
+            } else {
+                let val = env::var(var).unwrap_or_default();
+                result = format!("{}{val}{}", &result[..start], &result[start + 2 + end..]);
+                idx = start + val.len();
+            }
+        } else {
+            break;
+        }
+    }
+    result
+}
+
+/// Build a command that runs `cmdline` through the platform shell.
+fn shell_command(cmdline: &str) -> Command {
+    if cfg!(target_os = "windows") {
+        let mut c = Command::new("cmd");
+        c.arg("/C").arg(cmdline);
+        c
+    } else {
+        let mut c = Command::new("sh");
+        c.arg("-c").arg(cmdline);
+        c
+    }
+}
+
+
+fn run_post_execution_apps(apps: &[String], logger: &mut Logger) {
+    for app in apps {
+        let expanded = expand_env(app);
+        logger.log(&format!("Trying to execute {expanded}"));
+        let mut command = shell_command(&expanded);
+
+        match command.spawn().and_then(|mut c| c.wait()) {
+            Ok(s) => logger.log(&format!(
+                "Executed {expanded} with code {}",
+                s.code().unwrap_or(-1)
+            )),
+            Err(e) => logger.log(&format!("Failed to execute {expanded}: {e}")),
+        }
+    }
+}
+
+/// Run `source_command` and download the image it names. The command prints
+/// either a bare URL or a JSON object `{"url", "title", "copyright"}` to stdout.
+fn download_command_image(
+    config: &Config,
+    image_path: &Path,
+    download_options: &DownloadOptions,
+    logger: &mut Logger,
+) -> Option<Metadata> {
+    let expanded = expand_env(&config.source_command);
+    if expanded.trim().is_empty() {
+        logger.log("source is \"command\" but source_command is empty");
+        return None;
+    }
+
+    logger.log(&format!("Running source command {expanded}"));
+    let output = match shell_command(&expanded).output() {
+        Ok(o) => o,
+        Err(e) => {
+            logger.log(&format!("Failed to execute source command: {e}"));
+            return None;
+        }
+    };
+    if !output.status.success() {
+        logger.log(&format!(
+            "Source command exited with code {}: {}",
+            output.status.code().unwrap_or(-1),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+        return None;
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stdout = stdout.trim();
+    let (url, title, copyright) = if stdout.starts_with('{') {
+        let v: serde_json::Value = match serde_json::from_str(stdout) {
+            Ok(v) => v,
+            Err(e) => {
+                logger.log(&format!("Source command printed invalid JSON: {e}"));
+                return None;
+            }
+        };
+        let field = |key: &str| v[key].as_str().unwrap_or_default().trim().to_string();
+        (field("url"), field("title"), field("copyright"))
+    } else {
+        let url = stdout.lines().next().unwrap_or_default().trim().to_string();
+        (url, String::new(), String::new())
+    };
+    if url.is_empty() {
+        logger.log("Source command did not print an image URL");
+        return None;
+    }
+
+    if let Err(e) = download_file(&url, image_path, logger, download_options) {
+        logger.log(&format!("Failed to download image: {e}"));
+        return None;
+    }
+
+    let today = Local::now().format("%Y%m%d").to_string();
+    Some(Metadata {
+        urlbase: url.clone(),
+        url,
+        title,
+        copyright,
+        startdate: today.clone(),
+        enddate: today,
+        market: String::new(),
+        duplicate_of: None,
+    })
+}
+
+/// Fetch today's Bing image into `image_path`, reusing the previous day's file
+/// when Bing serves the same picture again.
+fn download_bing_image(
+    config: &Config,
+    folder: &Path,
+    name: &str,
+    image_path: &Path,
+    min_dims: (u32, u32),
+    download_options: &DownloadOptions,
+    logger: &mut Logger,
+) -> Option<Metadata> {
+    let api_json = folder.join(name).join("api.json");
+    let mut metadata = match fetch_metadata(config, &api_json, download_options, logger) {
+        Some(m) => m,
+        None => {
+            logger.log("Failed to get a download link from any market");
+            return None;
+        }
+    };
+
+    let previous = previous_day_folder(folder, name, &config.date_format);
+    let reused = previous.as_ref().is_some_and(|(prev_name, prev_folder)| {
+        reuse_previous_image(prev_name, prev_folder, &metadata.urlbase, image_path, min_dims, logger)
+    });
+
+    if reused {
+        metadata.duplicate_of = previous.as_ref().map(|(n, _)| n.clone());
+        return Some(metadata);
+    }
+
+    if let Err(e) = download_file(&metadata.url, image_path, logger, download_options) {
+        logger.log(&format!("Failed to download image: {e}"));
+        return None;
+    }
+
+    if let Some((prev_name, prev_folder)) = &previous {
+        if detect_duplicate_content(prev_name, prev_folder, image_path) {
+            logger.log(&format!("Downloaded image content is a duplicate of {prev_name}"));
+            metadata.duplicate_of = Some(prev_name.clone());
+        }
+    }
+    Some(metadata)
+}
+
+// ── Main logic ───────────────────────────────────────────────────────────────
+
+/// Why a run failed; the discriminant is the process exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// API or image download failed, or the image was unusable.
+    Download = 1,
+    /// The wallpaper could not be set.
+    Wallpaper = 2,
+    /// Config or filesystem error.
+    Io = 3,
+    /// The wallpaper was set but the registry still names another file.
+    WallpaperMismatch = 4,
+    /// Stopped early on Ctrl-C / SIGTERM.
+    Interrupted = shutdown::EXIT_CODE as isize,
+}
+
+impl Error {
+    pub fn exit_code(self) -> i32 {
+        self as i32
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Download => write!(f, "image download failed"),
+            Error::Wallpaper => write!(f, "wallpaper could not be set"),
+            Error::Io => write!(f, "filesystem error"),
+            Error::WallpaperMismatch => write!(f, "wallpaper was set but another file is still active"),
+            Error::Interrupted => write!(f, "stopped by shutdown request"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Outcome of a successful run, mirroring today's `status.json`.
+#[derive(Debug, Clone, Default)]
+pub struct RunReport {
+    /// Nothing was done: today's run had already completed or was too recent.
+    pub skipped: bool,
+    pub downloaded: bool,
+    pub cropped: bool,
+    pub watermark_added: bool,
+    pub adjusted: bool,
+    pub captioned: bool,
+    pub wallpaper_set: bool,
+    /// Today's image, e.g. `<data_dir>\2026.02.15\2026.02.15.jpg`.
+    pub image_path: PathBuf,
+    /// The image set as wallpaper; another day's with `pick_from_recent_days`.
+    pub applied_path: PathBuf,
+}
+
+impl RunReport {
+    fn new(status: &Status, image_path: &Path, skipped: bool) -> Self {
+        Self {
+            skipped,
+            downloaded: status.downloaded,
+            cropped: status.cropped,
+            watermark_added: status.watermark_added,
+            adjusted: status.adjusted,
+            captioned: status.captioned,
+            wallpaper_set: status.wallpaper_set,
+            image_path: image_path.to_path_buf(),
+            applied_path: status.applied_image.as_ref().map_or_else(|| image_path.to_path_buf(), PathBuf::from),
+        }
+    }
+}
+
+/// Remove leftover `.tmp` files from the day folders. Returns the bytes freed.
+fn remove_leftover_tmp(folder: &Path, date_format: &str, logger: &mut Logger) -> u64 {
+    let mut freed = 0;
+    for (_, day) in dated_folders(folder, date_format) {
+        let Ok(entries) = fs::read_dir(&day) else { continue };
+        for path in entries.flatten().map(|e| e.path()) {
+            if !path.extension().is_some_and(|x| x.eq_ignore_ascii_case("tmp")) {
+                continue;
+            }
+            let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            if fs::remove_file(&path).is_ok() {
+                logger.log(&format!("Removed leftover {}", path.display()));
+                freed += size;
+            }
+        }
+    }
+    freed
+}
+
+/// Check that the data volume has `min_free_mb` available before a download.
+/// Archiving (and `archive_max_width` shrinking) has already run by then, so
+/// what is left to reclaim is leftover temp files. False means too little space.
+fn ensure_free_space(folder: &Path, config: &Config, logger: &mut Logger) -> bool {
+    const MB: u64 = 1024 * 1024;
+    let min_free_mb = config.min_free_mb;
+    if min_free_mb == 0 {
+        return true;
+    }
+    let Some(free) = disk::free_bytes(folder) else {
+        logger.log("Free disk space unknown, skipping min_free_mb check");
+        return true;
+    };
+    if free >= min_free_mb as u64 * MB {
+        return true;
+    }
+
+    logger.log(&format!(
+        "Only {} MB free on the data volume (min_free_mb={min_free_mb}), cleaning up before downloading",
+        free / MB
+    ));
+    remove_leftover_tmp(folder, &config.date_format, logger);
+    let free = disk::free_bytes(folder).unwrap_or(free);
+    if free >= min_free_mb as u64 * MB {
+        logger.log(&format!("{} MB free after cleanup, continuing", free / MB));
+        return true;
+    }
+    logger.log(&format!(
+        "Not enough disk space: {} MB free, min_free_mb={min_free_mb}. Free up space{} and run again",
+        free / MB,
+        if config.archive_max_width == 0 { " (archive_max_width can shrink archived images)" } else { "" }
+    ));
+    false
+}
+
+/// Note a requested shutdown; everything saved so far stays consistent.
+fn interrupted(logger: &mut Logger) -> Error {
+    logger.log("Shutdown requested, stopping before the next step");
+    Error::Interrupted
+}
+
+/// Run the whole pipeline once with `config`, keeping state and the log in
+/// `data_dir`, fetching from `source` (`source::for_config` for the configured
+/// one). Safe to call repeatedly: with `chk` set, a completed day is reported
+/// as skipped.
+pub fn run_once(config: &Config, data_dir: &Path, source: &dyn ImageSource) -> Result<RunReport, Error> {
+    let name = date_name(Local::now().date_naive(), &config.date_format);
+    let dfolder = data_dir.join(&name);
+    if fs::create_dir_all(&dfolder).is_err() {
+        return Err(Error::Io);
+    }
+    let mut logger = Logger::new(&dfolder.join(format!("{name}.log")));
+    logger.log("********************Log Start********************");
+    let result = run_with_source(config, data_dir, source, &mut logger);
+    logger.log("*********************Log End*********************");
+    result
+}
+
+/// [`run_once`] with the configured source, writing to an existing logger.
+pub fn run(config: &Config, folder: &Path, logger: &mut Logger) -> Result<RunReport, Error> {
+    run_with_source(config, folder, &*source::for_config(config), logger)
+}
+
+/// [`run`] fetching from `source` instead of the configured one.
+pub fn run_with_source(
+    config: &Config,
+    folder: &Path,
+    source: &dyn ImageSource,
+    logger: &mut Logger,
+) -> Result<RunReport, Error> {
+    let name = date_name(Local::now().date_naive(), &config.date_format);
+    let dfolder = folder.join(&name);
+    if let Err(e) = fs::create_dir_all(&dfolder) {
+        logger.log(&format!("Failed to create {}: {e}", dfolder.display()));
+        return Err(Error::Io);
+    }
+
+    // Held until run() returns; a second instance bows out here.
+    let Some(_lock) = lock::RunLock::acquire(folder, logger) else {
+        return Ok(RunReport { skipped: true, ..Default::default() });
+    };
+
+    let result = run_day(config, folder, &name, source, logger);
+    if config.history && !result.as_ref().is_ok_and(|r| r.skipped) {
+        history::record(folder, &name, config, result.as_ref().err(), logger);
+    }
+    result
+}
+
+/// The steps of [`run`] for the day folder `name`, with the run lock held.
+fn run_day(
+    config: &Config,
+    folder: &Path,
+    name: &str,
+    source: &dyn ImageSource,
+    logger: &mut Logger,
+) -> Result<RunReport, Error> {
+    let dfolder = folder.join(name);
+    let archive_path = folder.join("Archive");
+    let status_file = dfolder.join("status.json");
+    let metadata_file = dfolder.join("metadata.json");
+    let base_path = get_base_path();
+    let mut status = load_status(&status_file);
+
+    // Today's image name; follow a changed filename_pattern by renaming.
+    let mut image_path = dfolder.join(resolve_filename(config, name, load_metadata(&metadata_file).as_ref()));
+    let previous = day_image(&dfolder, name);
+    if previous != image_path && previous.exists() && !image_path.exists() && rename_image(&previous, &image_path, logger) {
+        status.image_file = image_path.file_name().map(|f| f.to_string_lossy().into_owned());
+        save_status(&status_file, &status);
+    }
+
+    // Archive old folders
+    archive_old_folders(folder, &archive_path, logger, ARCHIVE_DAYS, config.archive_max_width, &config.date_format);
+
+    // Log config summary
+    let wm_details = if config.watermarks.is_empty() {
+        "No watermarks configured".into()
+    } else {
+        config
+            .watermarks
+            .iter()
+            .enumerate()
+            .map(|(i, wm)| format!("Watermark {}: {}", i + 1, wm.summary()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    logger.log(&format!(
+        "Config: data_dir={}, date_format={:?}, source={}, idx={}, mkt={}, mkt_fallbacks={:?}, fallback_to_previous={}, chk={}, min_interval_hours={}, pick_from_recent_days={}, archive_max_width={}, min_free_mb={}, ctd={}, set_lock_screen={}, transition={}, wtm={}, watermark_days={:?}, retry_delay={}, retry_count={}, max_retry_after={}, max_bytes_per_sec={}, connect_timeout_secs={}, read_timeout_secs={}, total_timeout_secs={}, ip_version={}, min_width={}, min_height={}, gamma_correct={}, aspect_crop={:?}, copyright_watermark={}, {wm_details}, safe_area=({}), post_execution_apps={:?}, copy_to_paths={:?}, history={}",
+        folder.display(), config.date_format, config.source, config.idx, config.mkt, config.mkt_fallbacks, config.fallback_to_previous,
+        config.chk, config.min_interval_hours, config.pick_from_recent_days, config.archive_max_width, config.min_free_mb, config.ctd, config.set_lock_screen, config.transition, config.wtm, config.watermark_days,
+        config.retry_delay, config.retry_count, config.max_retry_after, config.max_bytes_per_sec,
+        config.connect_timeout_secs, config.read_timeout_secs, config.total_timeout_secs, config.ip_version,
+        config.min_width, config.min_height,
+        config.gamma_correct, config.aspect_crop, config.copyright_watermark.enabled,
+        config.safe_area, config.post_execution_apps, config.copy_to_paths, config.history,
+    ));
+
+    let min_dims = (config.min_width, config.min_height);
+    let settings_hash = config.output_hash();
+
+    // Skip if the last successful run was too recent
+    if config.min_interval_hours > 0 {
+        let last = status
+            .completed_time
+            .as_deref()
+            .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok());
+        if let Some(last) = last {
+            let elapsed = Local::now().signed_duration_since(last);
+            if elapsed < chrono::Duration::hours(config.min_interval_hours as i64) {
+                logger.log(&format!(
+                    "Last successful run was {} minutes ago (min_interval_hours={}), skipping",
+                    elapsed.num_minutes(),
+                    config.min_interval_hours
+                ));
+                return Ok(RunReport::new(&status, &image_path, true));
+            }
+        }
+    }
+
+    // Skip if already completed
+    if config.chk && check_already_completed(&dfolder, &image_path, min_dims, &settings_hash, logger) {
+        return Ok(RunReport::new(&status, &image_path, true));
+    }
+    // check_already_completed may have reset flags on disk.
+    status = load_status(&status_file);
+
+    // Download if needed
+    if shutdown::requested() {
+        return Err(interrupted(logger));
+    }
+    if let Some(info) = inspect_image(&image_path, min_dims, logger) {
+        logger.log(&format!("Using existing valid image file ({info})"));
+    } else {
+        if !ensure_free_space(folder, config, logger) {
+            return Err(Error::Io);
+        }
+        let ctx = SourceContext {
+            config,
+            folder,
+            name,
+            min_dims,
+            download_options: DownloadOptions::from_config(config),
+        };
+        logger.log(&format!("Fetching image from {} source", source.name()));
+        let metadata = source.fetch(&ctx, &image_path, logger);
+        let metadata = match metadata {
+            Some(m) => m,
+            None if shutdown::requested() => return Err(interrupted(logger)),
+            None => return Err(Error::Download),
+        };
+
+        let Some(info) = inspect_image(&image_path, min_dims, logger) else {
+            logger.log("Downloaded image is corrupted or too small, aborting");
+            let _ = fs::remove_file(&image_path);
+            return Err(Error::Download);
+        };
+        save_metadata(&metadata_file, &metadata);
+
+        // Title and market are known now.
+        let resolved = dfolder.join(resolve_filename(config, name, Some(&metadata)));
+        if resolved != image_path && rename_image(&image_path, &resolved, logger) {
+            image_path = resolved;
+        }
+
+        status.downloaded = true;
+        status.download_time = Some(Local::now().to_rfc3339());
+        save_status(&status_file, &status);
+        logger.log(&format!("Image downloaded and verified ({info})"));
+    }
+    let image_file = image_path.file_name().map(|f| f.to_string_lossy().into_owned());
+    if status.image_file != image_file {
+        logger.log(&format!(
+            "Image file name: {} (filename_pattern {})",
+            image_file.as_deref().unwrap_or_default(),
+            config.filename_pattern
+        ));
+        status.image_file = image_file;
+        save_status(&status_file, &status);
+    }
+
+    if shutdown::requested() {
+        return Err(interrupted(logger));
+    }
+
+    // Post-processing (crop, watermarks, then colour adjustments). Cropping
+    // comes first so watermarks land on the visible region; the caption bar
+    // comes last so adjustments don't recolour it.
+    let original = original_path(&image_path);
+    let settings_changed = status.settings_hash.as_deref().is_some_and(|h| h != settings_hash);
+    let processed = status.cropped || status.watermark_added || status.adjusted || status.captioned;
+    if settings_changed && original.exists() {
+        // Start over from the clean copy so processing never stacks.
+        match fs::copy(&original, &image_path) {
+            Ok(_) => {
+                logger.log("Image processing settings changed, restored image from original");
+                status.cropped = false;
+                status.watermark_added = false;
+                status.adjusted = false;
+                status.captioned = false;
+                status.settings_hash = None;
+                let _ = fs::remove_file(watermark_hash_path(&image_path));
+                save_status(&status_file, &status);
+            }
+            Err(e) => logger.log(&format!("Failed to restore original image: {e}")),
+        }
+    } else if settings_changed && processed {
+        logger.log("Image processing settings changed but no original image is saved, keeping current image");
+    }
+
+    let apply_crop = !config.aspect_crop.is_empty() && !status.cropped;
+    let weekday = Local::now().weekday();
+    let watermark_today = config.wtm && config.watermark_active_on(weekday);
+    if config.wtm && !watermark_today {
+        logger.log(&format!("Watermarking skipped: {weekday} is not in watermark_days {:?}", config.watermark_days));
+    }
+    let mut apply_watermarks = watermark_today && !status.watermark_added;
+    if apply_watermarks && is_watermarked_output(&image_path) {
+        logger.log("Image is unchanged since it was watermarked, skipping watermarks");
+        status.watermark_added = true;
+        apply_watermarks = false;
+    }
+    let apply_adjust = !config.adjustments.is_noop() && !status.adjusted;
+    let apply_caption = config.caption_bar.enabled && !status.captioned;
+    let any_processing = apply_crop || apply_watermarks || apply_adjust || apply_caption;
+    if any_processing && !original.exists() {
+        match fs::copy(&image_path, &original) {
+            Ok(_) => logger.log(&format!("Original image saved as {}", original.display())),
+            Err(e) => logger.log(&format!("Failed to save original: {e}")),
+        }
+    }
+
+    if apply_crop {
+        status.cropped = crop_to_aspect(&image_path, &config.aspect_crop, logger);
+    }
+    if apply_watermarks {
+        // Start from the clean original so a watermark can never be applied
+        // twice, unless cropping has already rewritten the live image.
+        let source = if status.cropped || !original.exists() { &image_path } else { &original };
+        add_watermarks(source, &image_path, config, &base_path, logger);
+        status.watermark_added = true;
+        // The re-encoded file is written in place; never set a truncated one.
+        if let Some(info) = inspect_image(&image_path, min_dims, logger) {
+            logger.log(&format!("Watermarked image: {info}"));
+            if let Some(hash) = sha256_hex(&image_path) {
+                let _ = fs::write(watermark_hash_path(&image_path), hash);
+            }
+        } else {
+            status.cropped = false;
+            status.watermark_added = false;
+            let _ = fs::remove_file(watermark_hash_path(&image_path));
+            if !original.exists() || fs::copy(&original, &image_path).is_err() {
+                logger.log("Watermarked image is corrupted and no original could be restored, aborting");
+                let _ = fs::remove_file(&image_path);
+                save_status(&status_file, &status);
+                return Err(Error::Io);
+            }
+            logger.log("Watermarked image is corrupted, restored image from original without processing");
+        }
+    }
+    if apply_adjust {
+        status.adjusted = apply_adjustments(&image_path, &config.adjustments, logger);
+    }
+    if apply_caption {
+        let caption = load_metadata(&metadata_file)
+            .map(|m| match (m.title.trim(), m.copyright.trim()) {
+                (title, "") => title.to_string(),
+                ("", copyright) => copyright.to_string(),
+                (title, copyright) => format!("{title} — {copyright}"),
+            })
+            .unwrap_or_default();
+        if caption.is_empty() {
+            logger.log("Caption bar skipped: no title or copyright for today's image");
+        } else {
+            status.captioned =
+                add_caption_bar(&image_path, &config.caption_bar, &caption, &base_path, config.gamma_correct, logger);
+        }
+    }
+    if any_processing {
+        status.settings_hash = Some(settings_hash.clone());
+        save_status(&status_file, &status);
+    }
+
+    // Copy to configured paths
+    for dest in &config.copy_to_paths {
+        let expanded = expand_env(&dest.path);
+        let ep = Path::new(&expanded);
+        let target = if ep.extension().is_some() {
+            PathBuf::from(&expanded)
+        } else {
+            let _ = fs::create_dir_all(&expanded);
+            PathBuf::from(&expanded).join(image_path.file_name().unwrap_or_default())
+        };
+        // Without processing there is no separate original; both variants are the same file.
+        let (source, variant) = if dest.variant == "original" && original.exists() {
+            (&original, "original")
+        } else {
+            (&image_path, "processed")
+        };
+        match fs::copy(source, &target) {
+            Ok(_) => logger.log(&format!("Image ({variant}) copied to {}", target.display())),
+            Err(e) => logger.log(&format!("Failed to copy image to {expanded}: {e}")),
+        }
+    }
+
+    // Optionally apply a random recent day's image instead of today's
+    let mut applied_path = image_path.clone();
+    if config.pick_from_recent_days > 1 {
+        match pick_recent_image(folder, name, config.pick_from_recent_days, &config.date_format, min_dims, logger) {
+            Some((day, path)) => {
+                logger.log(&format!(
+                    "Downloaded {name}, applying {day} (random pick from the last {} days)",
+                    config.pick_from_recent_days
+                ));
+                applied_path = path;
+            }
+            None => logger.log("No valid recent image found, applying today's image"),
+        }
+    }
+
+    if shutdown::requested() {
+        return Err(interrupted(logger));
+    }
+
+    // Set wallpaper, unless the same picture is already showing
+    let image_dhash = image_hash::file_dhash(&applied_path);
+    let already_showing = image_dhash
+        .is_some_and(|h| showing_identical_image(&applied_path, h, status.wallpaper_hash.as_deref(), logger));
+    let wallpaper_result = if already_showing {
+        Ok(())
+    } else if config.transition == "fade" {
+        fade_transition(&applied_path, &dfolder, logger)
+    } else {
+        set_wallpaper(&applied_path, logger)
+    };
+    let wallpaper_ok = wallpaper_result.is_ok();
+    status.wallpaper_set = wallpaper_ok;
+    if wallpaper_ok {
+        status.wallpaper_hash = image_dhash.map(|h| format!("{h:016x}"));
+        status.applied_image = (applied_path != image_path).then(|| applied_path.display().to_string());
+    }
+
+    if let Err(e) = &wallpaper_result {
+        logger.log(&format!("Warning: Wallpaper setting may have failed ({e}), will retry next run"));
+    }
+
+    // Lock screen failures are logged but never fail the run.
+    if config.set_lock_screen {
+        if let Err(e) = set_lock_screen(&applied_path, logger) {
+            logger.log(&format!("Warning: Lock screen image was not updated ({e})"));
+        }
+    }
+
+    // Copy to desktop
+    if config.ctd {
+        copy_to_desktop(&image_path, logger);
+    }
+
+    // Post-execution apps
+    run_post_execution_apps(&config.post_execution_apps, logger);
+
+    // Mark completed
+    if wallpaper_ok {
+        status.completed = true;
+        status.completed_time = Some(Local::now().to_rfc3339());
+        status.settings_hash = Some(settings_hash);
+    }
+    save_status(&status_file, &status);
+
+    match wallpaper_result {
+        Ok(()) => {
+            logger.log("All tasks completed");
+            Ok(RunReport::new(&status, &image_path, false))
+        }
+        Err(WallpaperError::Mismatch { .. }) => Err(Error::WallpaperMismatch),
+        Err(_) => Err(Error::Wallpaper),
+    }
+}
@@ -0,0 +1,137 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::logger::Logger;
+use crate::metadata::Metadata;
+use crate::postprocess::save_jpeg;
+
+/// Selection state persisted between runs so sequential/random picks advance daily.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LocalState {
+    #[serde(default)]
+    last_file: Option<String>,
+}
+
+fn load_state(path: &Path) -> LocalState {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+fn save_state(path: &Path, state: &LocalState) {
+    if let Ok(json) = serde_json::to_string_pretty(state) {
+        let _ = fs::write(path, json);
+    }
+}
+
+fn is_jpeg(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| matches!(e.to_ascii_lowercase().as_str(), "jpg" | "jpeg"))
+}
+
+/// Decodable image files in `folder`, sorted by name.
+fn list_images(folder: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(folder)
+        .map(|rd| {
+            rd.flatten()
+                .map(|e| e.path())
+                .filter(|p| p.is_file())
+                .filter(|p| {
+                    is_jpeg(p)
+                        || p.extension().and_then(|e| e.to_str()).is_some_and(|e| e.eq_ignore_ascii_case("png"))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files
+}
+
+/// xorshift64 seeded from the clock; good enough to vary a daily pick.
+pub(crate) fn random_index(len: usize) -> usize {
+    let mut x = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0x9e37_79b9_7f4a_7c15, |d| d.as_nanos() as u64)
+        | 1;
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    (x % len as u64) as usize
+}
+
+/// Pick an image from `folder` according to `policy` (`random`, `sequential`
+/// or `by-date`) and place it at `image_path` as JPEG.
+pub fn pick_local_image(
+    folder: &Path,
+    policy: &str,
+    state_path: &Path,
+    image_path: &Path,
+    logger: &mut Logger,
+) -> Option<Metadata> {
+    let files = list_images(folder);
+    if files.is_empty() {
+        logger.log(&format!("No images found in local_folder {}", folder.display()));
+        return None;
+    }
+
+    let mut state = load_state(state_path);
+    let last = state
+        .last_file
+        .as_ref()
+        .and_then(|name| files.iter().position(|f| f.file_name().is_some_and(|n| n.to_string_lossy() == *name)));
+
+    let index = match policy {
+        "sequential" => last.map_or(0, |i| (i + 1) % files.len()),
+        "by-date" => {
+            let days = Local::now().date_naive().signed_duration_since(chrono::NaiveDate::default()).num_days();
+            days.rem_euclid(files.len() as i64) as usize
+        }
+        _ => {
+            let i = random_index(files.len());
+            // Avoid showing the same picture two days running.
+            if files.len() > 1 && Some(i) == last { (i + 1) % files.len() } else { i }
+        }
+    };
+    let chosen = &files[index];
+    logger.log(&format!(
+        "Picked local image {} ({policy}, {}/{})",
+        chosen.display(),
+        index + 1,
+        files.len()
+    ));
+
+    let placed = if is_jpeg(chosen) {
+        fs::copy(chosen, image_path).map(|_| ()).map_err(|e| e.to_string())
+    } else {
+        image::open(chosen)
+            .map_err(|e| e.to_string())
+            .and_then(|img| save_jpeg(img, image_path).map_err(|e| e.to_string()))
+    };
+    if let Err(e) = placed {
+        logger.log(&format!("Failed to copy local image {}: {e}", chosen.display()));
+        return None;
+    }
+
+    let file_name = chosen.file_name().map(|n| n.to_string_lossy().into_owned());
+    state.last_file = file_name.clone();
+    save_state(state_path, &state);
+
+    let today = Local::now().format("%Y%m%d").to_string();
+    let path_str = chosen.display().to_string();
+    Some(Metadata {
+        urlbase: path_str.clone(),
+        url: path_str,
+        title: chosen.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default(),
+        copyright: String::new(),
+        startdate: today.clone(),
+        enddate: today,
+        market: String::new(),
+        duplicate_of: None,
+    })
+}
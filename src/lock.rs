@@ -0,0 +1,108 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+
+use crate::logger::Logger;
+
+/// A lock older than this is reclaimed even if its owner still seems alive.
+const STALE_HOURS: i64 = 3;
+
+// ── Process liveness ─────────────────────────────────────────────────────────
+
+#[cfg(windows)]
+const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+#[cfg(windows)]
+const STILL_ACTIVE: u32 = 259;
+#[cfg(windows)]
+const ERROR_ACCESS_DENIED: u32 = 5;
+
+#[cfg(windows)]
+#[link(name = "kernel32")]
+extern "system" {
+    fn OpenProcess(dwDesiredAccess: u32, bInheritHandle: i32, dwProcessId: u32) -> isize;
+    fn GetExitCodeProcess(hProcess: isize, lpExitCode: *mut u32) -> i32;
+    fn CloseHandle(hObject: isize) -> i32;
+    fn GetLastError() -> u32;
+}
+
+#[cfg(windows)]
+fn process_alive(pid: u32) -> bool {
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle == 0 {
+            // A process we may not query still exists.
+            return GetLastError() == ERROR_ACCESS_DENIED;
+        }
+        let mut code = 0u32;
+        let ok = GetExitCodeProcess(handle, &mut code) != 0;
+        CloseHandle(handle);
+        ok && code == STILL_ACTIVE
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn process_alive(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+/// No cheap check elsewhere; rely on the lock's age.
+#[cfg(not(any(windows, target_os = "linux")))]
+fn process_alive(_pid: u32) -> bool {
+    true
+}
+
+// ── Run lock ─────────────────────────────────────────────────────────────────
+
+/// Exclusive `run.lock` in the data folder, holding the owner's PID and start
+/// time. Removed on drop. Note `process::exit` and `panic = "abort"` skip
+/// destructors; a lock left behind that way is reclaimed once its PID is gone.
+pub struct RunLock {
+    path: PathBuf,
+}
+
+impl RunLock {
+    /// Take the lock, reclaiming a stale one. `None` means another run holds it.
+    pub fn acquire(folder: &Path, logger: &mut Logger) -> Option<RunLock> {
+        let path = folder.join("run.lock");
+        for _ in 0..2 {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut f) => {
+                    let _ = writeln!(f, "{}\n{}", std::process::id(), Local::now().to_rfc3339());
+                    return Some(RunLock { path });
+                }
+                Err(_) => {
+                    let content = fs::read_to_string(&path).unwrap_or_default();
+                    let mut lines = content.lines();
+                    let pid = lines.next().and_then(|l| l.trim().parse::<u32>().ok());
+                    let since = lines.next().and_then(|l| DateTime::parse_from_rfc3339(l.trim()).ok());
+                    let expired =
+                        since.is_none_or(|t| Local::now().signed_duration_since(t) > chrono::Duration::hours(STALE_HOURS));
+                    let owner_gone = pid.is_none_or(|p| !process_alive(p));
+                    if !expired && !owner_gone {
+                        logger.log(&format!(
+                            "Another run in progress (PID {}, since {}), exiting",
+                            pid.unwrap_or_default(),
+                            since.map(|t| t.to_rfc3339()).unwrap_or_default()
+                        ));
+                        return None;
+                    }
+                    logger.log(&format!(
+                        "Reclaiming stale run lock ({})",
+                        if owner_gone { "owner no longer running" } else { "older than 3 hours" }
+                    ));
+                    let _ = fs::remove_file(&path);
+                }
+            }
+        }
+        logger.log(&format!("Failed to create {}", path.display()));
+        None
+    }
+}
+
+impl Drop for RunLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
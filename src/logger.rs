@@ -8,6 +8,10 @@ use chrono::Local;
 pub struct Logger {
     path: PathBuf,
     initialized: bool,
+    /// Discard every message (see [`Logger::silent`]).
+    silent: bool,
+    /// Values masked in every message (see [`Logger::redact`]).
+    secrets: Vec<String>,
 }
 
 impl Logger {
@@ -15,12 +19,34 @@ impl Logger {
         Self {
             path: path.to_path_buf(),
             initialized: false,
+            silent: false,
+            secrets: Vec::new(),
+        }
+    }
+
+    /// Logger that writes nothing, for checks whose complaints aren't worth reporting.
+    pub fn silent() -> Self {
+        Self {
+            path: PathBuf::new(),
+            initialized: true,
+            silent: true,
+            secrets: Vec::new(),
+        }
+    }
+
+    /// Mask `secret` as `***` in all later messages.
+    pub fn redact(&mut self, secret: &str) {
+        if !secret.is_empty() && !self.secrets.iter().any(|s| s == secret) {
+            self.secrets.push(secret.to_string());
         }
     }
 
     /// Write a timestamped message to the log file.
     /// On first call, adds a blank line separator if the file already has content.
     pub fn log(&mut self, message: &str) {
+        if self.silent {
+            return;
+        }
         if !self.initialized {
             if fs::metadata(&self.path).is_ok_and(|m| m.len() > 0) {
                 if let Ok(mut f) = OpenOptions::new().append(true).open(&self.path) {
@@ -32,7 +58,21 @@ impl Logger {
 
         if let Ok(mut f) = OpenOptions::new().create(true).append(true).open(&self.path) {
             let ts = Local::now().format("%Y-%m-%d %H:%M:%S");
+            let mut message = message.to_string();
+            for secret in &self.secrets {
+                message = message.replace(secret.as_str(), "***");
+            }
             let _ = writeln!(f, "[{ts}] {message}");
         }
     }
+
+    /// Like [`Logger::log`], but skipped when the log file already has the
+    /// message. Logs are per day, so a condition that persists across runs is
+    /// reported once a day.
+    pub fn log_once(&mut self, message: &str) {
+        if fs::read_to_string(&self.path).is_ok_and(|s| s.contains(message)) {
+            return;
+        }
+        self.log(message);
+    }
 }
@@ -0,0 +1,58 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Per-day image metadata extracted from the Bing API response.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Metadata {
+    #[serde(default)]
+    pub urlbase: String,
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub copyright: String,
+    #[serde(default)]
+    pub startdate: String,
+    #[serde(default)]
+    pub enddate: String,
+    #[serde(default)]
+    pub market: String,
+    /// Date folder holding an identical image, when one was detected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duplicate_of: Option<String>,
+}
+
+impl Metadata {
+    /// Build metadata from the first entry of a Bing `HPImageArchive` response.
+    pub fn from_api(v: &Value, market: &str) -> Option<Self> {
+        let img = &v["images"][0];
+        let urlbase = img["urlbase"].as_str()?.to_string();
+        let field = |key: &str| img[key].as_str().unwrap_or_default().to_string();
+        Some(Self {
+            url: format!("https://www.bing.com{urlbase}_UHD.jpg"),
+            urlbase,
+            title: field("title"),
+            copyright: field("copyright"),
+            startdate: field("startdate"),
+            enddate: field("enddate"),
+            market: market.to_string(),
+            duplicate_of: None,
+        })
+    }
+}
+
+pub fn load_metadata(path: &Path) -> Option<Metadata> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+}
+
+pub fn save_metadata(path: &Path, metadata: &Metadata) {
+    if let Ok(json) = serde_json::to_string_pretty(metadata) {
+        let _ = fs::write(path, json);
+    }
+}
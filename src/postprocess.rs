@@ -0,0 +1,180 @@
+use std::fs;
+use std::io::BufWriter;
+use std::path::Path;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops;
+use image::{DynamicImage, ImageEncoder, Rgba, RgbaImage};
+
+use crate::config::{Adjustments, IMAGE_QUALITY};
+use crate::logger::Logger;
+
+// ── JPEG output ──────────────────────────────────────────────────────────────
+
+/// Encode `img` as JPEG at `IMAGE_QUALITY`, replacing `path`.
+pub fn save_jpeg(img: DynamicImage, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let rgb = img.to_rgb8();
+    let file = fs::File::create(path)?;
+    let encoder = JpegEncoder::new_with_quality(BufWriter::new(file), IMAGE_QUALITY);
+    encoder.write_image(rgb.as_raw(), rgb.width(), rgb.height(), image::ExtendedColorType::Rgb8)?;
+    Ok(())
+}
+
+// ── Aspect crop ──────────────────────────────────────────────────────────────
+
+/// Parse a `width:height` ratio such as `21:9`.
+pub fn parse_aspect_ratio(s: &str) -> Option<(u32, u32)> {
+    let (w, h) = s.trim().split_once(':')?;
+    let w = w.trim().parse::<u32>().ok().filter(|&n| n > 0)?;
+    let h = h.trim().parse::<u32>().ok().filter(|&n| n > 0)?;
+    Some((w, h))
+}
+
+/// Largest centred rectangle of ratio `rw:rh` inside `w×h`, as `(x, y, width, height)`.
+fn centered_crop_rect(w: u32, h: u32, rw: u32, rh: u32) -> (u32, u32, u32, u32) {
+    let (w64, h64, rw, rh) = (w as u64, h as u64, rw as u64, rh as u64);
+    let (cw, ch) = if w64 * rh > h64 * rw {
+        (h64 * rw / rh, h64)
+    } else {
+        (w64, w64 * rh / rw)
+    };
+    let (cw, ch) = (cw.max(1) as u32, ch.max(1) as u32);
+    ((w - cw) / 2, (h - ch) / 2, cw, ch)
+}
+
+/// Centre-crop the image file in place to the `ratio` (`width:height`).
+pub fn crop_to_aspect(image_path: &Path, ratio: &str, logger: &mut Logger) -> bool {
+    let (rw, rh) = match parse_aspect_ratio(ratio) {
+        Some(r) => r,
+        None => {
+            logger.log(&format!("Invalid aspect_crop \"{ratio}\" (expected width:height), skipping crop"));
+            return false;
+        }
+    };
+    let mut img = match image::open(image_path) {
+        Ok(i) => i,
+        Err(e) => {
+            logger.log(&format!("Failed to open image for cropping: {e}"));
+            return false;
+        }
+    };
+
+    let (w, h) = (img.width(), img.height());
+    let (x, y, cw, ch) = centered_crop_rect(w, h, rw, rh);
+    if (cw, ch) == (w, h) {
+        logger.log(&format!("Image already matches aspect {rw}:{rh}, no crop needed"));
+        return true;
+    }
+    let cropped = img.crop(x, y, cw, ch);
+
+    match save_jpeg(cropped, image_path) {
+        Ok(()) => {
+            logger.log(&format!("Cropped {w}x{h} to {cw}x{ch} for aspect {rw}:{rh}"));
+            true
+        }
+        Err(e) => {
+            logger.log(&format!("Failed to save cropped image: {e}"));
+            false
+        }
+    }
+}
+
+// ── Colour adjustments ───────────────────────────────────────────────────────
+
+fn rgb_to_hsl(r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.0;
+    let d = max - min;
+    if d == 0.0 {
+        return (0.0, 0.0, l);
+    }
+    let s = if l > 0.5 { d / (2.0 - max - min) } else { d / (max + min) };
+    let h = if max == r {
+        (g - b) / d + if g < b { 6.0 } else { 0.0 }
+    } else if max == g {
+        (b - r) / d + 2.0
+    } else {
+        (r - g) / d + 4.0
+    };
+    (h / 6.0, s, l)
+}
+
+fn hue_to_rgb(p: f32, q: f32, mut t: f32) -> f32 {
+    if t < 0.0 {
+        t += 1.0;
+    }
+    if t > 1.0 {
+        t -= 1.0;
+    }
+    if t < 1.0 / 6.0 {
+        p + (q - p) * 6.0 * t
+    } else if t < 0.5 {
+        q
+    } else if t < 2.0 / 3.0 {
+        p + (q - p) * (2.0 / 3.0 - t) * 6.0
+    } else {
+        p
+    }
+}
+
+fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (f32, f32, f32) {
+    if s == 0.0 {
+        return (l, l, l);
+    }
+    let q = if l < 0.5 { l * (1.0 + s) } else { l + s - l * s };
+    let p = 2.0 * l - q;
+    (hue_to_rgb(p, q, h + 1.0 / 3.0), hue_to_rgb(p, q, h), hue_to_rgb(p, q, h - 1.0 / 3.0))
+}
+
+/// Scale HSL saturation of every pixel by `factor`.
+fn saturate(img: &mut RgbaImage, factor: f32) {
+    for Rgba(px) in img.pixels_mut() {
+        let (h, s, l) = rgb_to_hsl(px[0] as f32 / 255.0, px[1] as f32 / 255.0, px[2] as f32 / 255.0);
+        let (r, g, b) = hsl_to_rgb(h, (s * factor).clamp(0.0, 1.0), l);
+        px[0] = (r * 255.0).round() as u8;
+        px[1] = (g * 255.0).round() as u8;
+        px[2] = (b * 255.0).round() as u8;
+    }
+}
+
+/// Apply brightness, contrast, saturation and grayscale to the image file in place.
+pub fn apply_adjustments(image_path: &Path, adjustments: &Adjustments, logger: &mut Logger) -> bool {
+    let img = match image::open(image_path) {
+        Ok(i) => i,
+        Err(e) => {
+            logger.log(&format!("Failed to open image for adjustments: {e}"));
+            return false;
+        }
+    };
+
+    let mut rgba = img.to_rgba8();
+    if adjustments.brightness != 0 {
+        rgba = imageops::brighten(&rgba, adjustments.brightness);
+    }
+    if adjustments.contrast != 0.0 {
+        rgba = imageops::contrast(&rgba, adjustments.contrast);
+    }
+    if adjustments.saturation != 1.0 {
+        saturate(&mut rgba, adjustments.saturation);
+    }
+    let out = if adjustments.grayscale {
+        DynamicImage::ImageLuma8(imageops::grayscale(&rgba))
+    } else {
+        DynamicImage::ImageRgba8(rgba)
+    };
+
+    match save_jpeg(out, image_path) {
+        Ok(()) => {
+            logger.log(&format!(
+                "Adjustments applied: brightness={}, contrast={}, saturation={}, grayscale={}",
+                adjustments.brightness, adjustments.contrast, adjustments.saturation, adjustments.grayscale
+            ));
+            true
+        }
+        Err(e) => {
+            logger.log(&format!("Failed to save adjusted image: {e}"));
+            false
+        }
+    }
+}
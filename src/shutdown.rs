@@ -0,0 +1,44 @@
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Set by the Ctrl-C / termination handler; polled between steps.
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Exit code when a run stops on request (128 + SIGINT).
+pub const EXIT_CODE: i32 = 130;
+
+/// Granularity of interruptible sleeps.
+const POLL: Duration = Duration::from_millis(200);
+
+/// Install the Ctrl-C / SIGTERM handler. The first signal asks the run to stop
+/// at the next safe point; a second one exits immediately.
+pub fn install() {
+    let _ = ctrlc::set_handler(|| {
+        if REQUESTED.swap(true, Ordering::SeqCst) {
+            process::exit(EXIT_CODE);
+        }
+    });
+}
+
+/// Whether a shutdown has been requested.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Sleep for `duration`, waking early on a shutdown request.
+/// Returns false when interrupted.
+pub fn sleep(duration: Duration) -> bool {
+    let end = Instant::now() + duration;
+    loop {
+        if requested() {
+            return false;
+        }
+        let left = end.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return true;
+        }
+        thread::sleep(left.min(POLL));
+    }
+}
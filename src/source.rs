@@ -0,0 +1,112 @@
+use std::path::Path;
+
+use crate::config::Config;
+use crate::download::DownloadOptions;
+use crate::logger::Logger;
+use crate::metadata::Metadata;
+use crate::{himawari, local};
+
+/// Everything a source needs to know about the current run.
+pub struct SourceContext<'a> {
+    pub config: &'a Config,
+    /// Data folder root.
+    pub folder: &'a Path,
+    /// Today's folder name, e.g. `2026.02.15`.
+    pub name: &'a str,
+    pub min_dims: (u32, u32),
+    pub download_options: DownloadOptions,
+}
+
+impl SourceContext<'_> {
+    fn dfolder(&self) -> std::path::PathBuf {
+        self.folder.join(self.name)
+    }
+}
+
+/// Where today's image comes from. `run()` only talks to this trait, so a
+/// source can be swapped out without touching the processing pipeline.
+pub trait ImageSource {
+    /// Config name of the source, for log output.
+    fn name(&self) -> &'static str;
+
+    /// Place today's image at `image_path` and return its metadata.
+    fn fetch(&self, ctx: &SourceContext, image_path: &Path, logger: &mut Logger) -> Option<Metadata>;
+}
+
+pub struct BingSource;
+
+impl ImageSource for BingSource {
+    fn name(&self) -> &'static str {
+        "bing"
+    }
+
+    fn fetch(&self, ctx: &SourceContext, image_path: &Path, logger: &mut Logger) -> Option<Metadata> {
+        crate::download_bing_image(
+            ctx.config,
+            ctx.folder,
+            ctx.name,
+            image_path,
+            ctx.min_dims,
+            &ctx.download_options,
+            logger,
+        )
+    }
+}
+
+pub struct HimawariSource;
+
+impl ImageSource for HimawariSource {
+    fn name(&self) -> &'static str {
+        "himawari"
+    }
+
+    fn fetch(&self, ctx: &SourceContext, image_path: &Path, logger: &mut Logger) -> Option<Metadata> {
+        himawari::download_latest(
+            &ctx.dfolder(),
+            image_path,
+            ctx.config.himawari_level,
+            &ctx.download_options,
+            logger,
+        )
+    }
+}
+
+pub struct CommandSource;
+
+impl ImageSource for CommandSource {
+    fn name(&self) -> &'static str {
+        "command"
+    }
+
+    fn fetch(&self, ctx: &SourceContext, image_path: &Path, logger: &mut Logger) -> Option<Metadata> {
+        crate::download_command_image(ctx.config, image_path, &ctx.download_options, logger)
+    }
+}
+
+pub struct LocalSource;
+
+impl ImageSource for LocalSource {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn fetch(&self, ctx: &SourceContext, image_path: &Path, logger: &mut Logger) -> Option<Metadata> {
+        local::pick_local_image(
+            Path::new(&crate::expand_env(&ctx.config.local_folder)),
+            &ctx.config.local_policy,
+            &ctx.folder.join("local_state.json"),
+            image_path,
+            logger,
+        )
+    }
+}
+
+/// The source selected by `config.source`.
+pub fn for_config(config: &Config) -> Box<dyn ImageSource> {
+    match config.source.as_str() {
+        "himawari" => Box::new(HimawariSource),
+        "command" => Box::new(CommandSource),
+        "local" => Box::new(LocalSource),
+        _ => Box::new(BingSource),
+    }
+}
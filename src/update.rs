@@ -0,0 +1,183 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::download::{download_file, DownloadOptions};
+use crate::logger::Logger;
+
+/// Extension of the previous exe left behind by a swap; removed on next launch.
+const OLD_EXE_EXT: &str = "old";
+/// Extension of a downloaded exe before it is verified and swapped in.
+const NEW_EXE_EXT: &str = "new";
+
+/// Numeric components of a version tag such as `v1.2.3`.
+fn parse_version(tag: &str) -> Vec<u64> {
+    tag.trim()
+        .trim_start_matches(['v', 'V'])
+        .split(['.', '-', '+'])
+        .map_while(|part| part.parse().ok())
+        .collect()
+}
+
+fn is_newer(latest: &str, current: &str) -> bool {
+    let (mut a, mut b) = (parse_version(latest), parse_version(current));
+    let len = a.len().max(b.len());
+    a.resize(len, 0);
+    b.resize(len, 0);
+    a > b
+}
+
+/// Hex SHA-256 of a file's contents.
+pub(crate) fn sha256_hex(path: &Path) -> Option<String> {
+    let bytes = fs::read(path).ok()?;
+    Some(Sha256::digest(&bytes).iter().map(|b| format!("{b:02x}")).collect())
+}
+
+/// Find the expected hash for `file_name` in a `.sha256` file or a
+/// `sha256sum`-style list (`<hash>  <name>` per line).
+fn expected_hash(text: &str, file_name: &str) -> Option<String> {
+    let is_hash = |s: &str| s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit());
+    for line in text.lines() {
+        let mut parts = line.split_whitespace();
+        let Some(hash) = parts.next().filter(|h| is_hash(h)) else { continue };
+        match parts.next() {
+            None => return Some(hash.to_ascii_lowercase()),
+            Some(name) if name.trim_start_matches('*').eq_ignore_ascii_case(file_name) => {
+                return Some(hash.to_ascii_lowercase());
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Asset `(name, download url)` pairs from a GitHub release.
+fn release_assets(release: &Value) -> Vec<(String, String)> {
+    release["assets"]
+        .as_array()
+        .map(|assets| {
+            assets
+                .iter()
+                .filter_map(|a| Some((a["name"].as_str()?.to_string(), a["browser_download_url"].as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Remove the exe left over from a previous update, if any.
+pub fn cleanup_previous_update() {
+    if let Ok(exe) = env::current_exe() {
+        let _ = fs::remove_file(exe.with_extension(OLD_EXE_EXT));
+    }
+}
+
+/// Check `update_url` (a GitHub "latest release" API URL) and, if it names a
+/// newer version, download, verify and swap in the new exe. The running exe
+/// is renamed aside, since Windows will not overwrite it, so the update
+/// takes effect on next launch.
+pub fn self_update(update_url: &str, options: &DownloadOptions, logger: &mut Logger) -> bool {
+    let current = env!("CARGO_PKG_VERSION");
+    if update_url.trim().is_empty() {
+        logger.log("Self-update: update_url is not configured");
+        return false;
+    }
+    let exe = match env::current_exe() {
+        Ok(p) => p,
+        Err(e) => {
+            logger.log(&format!("Self-update: cannot locate the running exe: {e}"));
+            return false;
+        }
+    };
+
+    logger.log(&format!("Self-update: checking {update_url} (current version {current})"));
+    let release_json = env::temp_dir().join("AutoWallpaper-release.json");
+    if let Err(e) = download_file(update_url, &release_json, logger, options) {
+        logger.log(&format!("Self-update: failed to fetch release information: {e}"));
+        return false;
+    }
+    let release: Option<Value> = fs::read_to_string(&release_json)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok());
+    let _ = fs::remove_file(&release_json);
+    let Some(release) = release else {
+        logger.log("Self-update: release information is not valid JSON");
+        return false;
+    };
+
+    let Some(tag) = release["tag_name"].as_str() else {
+        logger.log("Self-update: release has no tag_name");
+        return false;
+    };
+    if !is_newer(tag, current) {
+        logger.log(&format!("Self-update: latest release {tag} is not newer than {current}, nothing to do"));
+        return true;
+    }
+    logger.log(&format!("Self-update: new version {tag} available"));
+
+    let assets = release_assets(&release);
+    let exe_name = exe.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let Some((asset_name, asset_url)) = assets
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(&exe_name))
+        .or_else(|| assets.iter().find(|(name, _)| name.to_ascii_lowercase().ends_with(".exe")))
+        .cloned()
+    else {
+        logger.log("Self-update: release has no .exe asset");
+        return false;
+    };
+    let sums_url = assets
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(&format!("{asset_name}.sha256")))
+        .or_else(|| assets.iter().find(|(name, _)| name.to_ascii_lowercase().starts_with("sha256sums")))
+        .map(|(_, url)| url.clone());
+    let Some(sums_url) = sums_url else {
+        logger.log(&format!("Self-update: no checksum published for {asset_name}, refusing to update"));
+        return false;
+    };
+
+    let new_exe = exe.with_extension(NEW_EXE_EXT);
+    let sums_path: PathBuf = env::temp_dir().join("AutoWallpaper-update.sha256");
+    logger.log(&format!("Self-update: downloading {asset_name}"));
+    let downloaded = download_file(&asset_url, &new_exe, logger, options)
+        .and_then(|()| download_file(&sums_url, &sums_path, logger, options));
+    if let Err(e) = downloaded {
+        logger.log(&format!("Self-update: download failed: {e}"));
+        let _ = fs::remove_file(&new_exe);
+        let _ = fs::remove_file(&sums_path);
+        return false;
+    }
+
+    let expected = fs::read_to_string(&sums_path).ok().and_then(|t| expected_hash(&t, &asset_name));
+    let _ = fs::remove_file(&sums_path);
+    let actual = sha256_hex(&new_exe);
+    match (&expected, &actual) {
+        (Some(e), Some(a)) if e == a => logger.log(&format!("Self-update: checksum verified ({a})")),
+        _ => {
+            logger.log(&format!(
+                "Self-update: checksum mismatch (expected {}, got {}), discarding download",
+                expected.as_deref().unwrap_or("none"),
+                actual.as_deref().unwrap_or("none")
+            ));
+            let _ = fs::remove_file(&new_exe);
+            return false;
+        }
+    }
+
+    let old_exe = exe.with_extension(OLD_EXE_EXT);
+    let _ = fs::remove_file(&old_exe);
+    if let Err(e) = fs::rename(&exe, &old_exe) {
+        logger.log(&format!("Self-update: failed to move the running exe aside: {e}"));
+        let _ = fs::remove_file(&new_exe);
+        return false;
+    }
+    if let Err(e) = fs::rename(&new_exe, &exe) {
+        logger.log(&format!("Self-update: failed to place the new exe: {e}"));
+        let _ = fs::rename(&old_exe, &exe);
+        return false;
+    }
+    logger.log(&format!("Self-update: updated to {tag}, takes effect on next launch"));
+    true
+}
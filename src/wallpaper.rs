@@ -1,18 +1,105 @@
+use std::fmt;
+#[cfg(windows)]
+use std::fs;
 use std::path::Path;
+#[cfg(windows)]
+use std::path::PathBuf;
+#[cfg(windows)]
+use std::thread;
+#[cfg(windows)]
+use std::time::{Duration, Instant};
+
+#[cfg(windows)]
+use image::imageops::FilterType;
+#[cfg(windows)]
+use image::{DynamicImage, RgbImage};
 
 use crate::logger::Logger;
+#[cfg(windows)]
+use crate::postprocess::save_jpeg;
+
+// ── Errors ───────────────────────────────────────────────────────────────────
+
+/// Why setting the desktop wallpaper or lock screen failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WallpaperError {
+    /// `SystemParametersInfoW` rejected the request.
+    #[cfg(windows)]
+    ApiFailed,
+    /// The call succeeded but the registry still names another file.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    Mismatch { expected: String, current: String },
+    /// Writing the lock-screen policy needs administrator rights.
+    #[cfg(windows)]
+    AccessDenied,
+    /// Any other registry failure, with its Win32 error code.
+    #[cfg(windows)]
+    Registry(i32),
+    /// Not available on this platform.
+    #[cfg(not(windows))]
+    Unsupported,
+}
+
+impl fmt::Display for WallpaperError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(windows)]
+            Self::ApiFailed => write!(f, "SystemParametersInfoW failed"),
+            Self::Mismatch { expected, current } => {
+                write!(f, "wallpaper path mismatch (expected {expected}, current {current})")
+            }
+            #[cfg(windows)]
+            Self::AccessDenied => write!(f, "administrator rights are required"),
+            #[cfg(windows)]
+            Self::Registry(code) => write!(f, "registry error {code}"),
+            #[cfg(not(windows))]
+            Self::Unsupported => write!(f, "only supported on Windows"),
+        }
+    }
+}
 
 // ── Windows API constants ────────────────────────────────────────────────────
 
+#[cfg(windows)]
 const SPI_SETDESKWALLPAPER: u32 = 0x0014;
+#[cfg(windows)]
 const SPIF_UPDATEINIFILE: u32 = 0x0001;
+#[cfg(windows)]
 const SPIF_SENDCHANGE: u32 = 0x0002;
+#[cfg(windows)]
 const HKEY_CURRENT_USER: isize = -2_147_483_647; // 0x8000_0001u32 as isize
+#[cfg(windows)]
 const KEY_READ: u32 = 0x0002_0019;
+#[cfg(windows)]
 const REG_SZ: u32 = 1;
+#[cfg(windows)]
+const REG_EXPAND_SZ: u32 = 2;
+#[cfg(windows)]
+const ERROR_MORE_DATA: i32 = 234;
+#[cfg(windows)]
+const ERROR_ACCESS_DENIED: i32 = 5;
+#[cfg(windows)]
+const HKEY_LOCAL_MACHINE: isize = -2_147_483_646; // 0x8000_0002u32 as isize
+#[cfg(windows)]
+const KEY_WRITE: u32 = 0x0002_0006;
+#[cfg(windows)]
+const KEY_WOW64_64KEY: u32 = 0x0100;
+#[cfg(windows)]
+const REG_DWORD: u32 = 4;
+
+/// Registry key read by the PersonalizationCSP lock-screen policy.
+#[cfg(windows)]
+const PERSONALIZATION_CSP: &str = r"SOFTWARE\Microsoft\Windows\CurrentVersion\PersonalizationCSP";
+
+/// How long `set_wallpaper` waits for the registry to reflect the change.
+#[cfg(windows)]
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(2);
+#[cfg(windows)]
+const VERIFY_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 // ── FFI declarations (avoids windows-sys dependency) ─────────────────────────
 
+#[cfg(windows)]
 #[link(name = "user32")]
 extern "system" {
     fn SystemParametersInfoW(
@@ -23,6 +110,7 @@ extern "system" {
     ) -> i32;
 }
 
+#[cfg(windows)]
 #[link(name = "advapi32")]
 extern "system" {
     fn RegOpenKeyExW(
@@ -40,27 +128,117 @@ extern "system" {
         lpData: *mut u8,
         lpcbData: *mut u32,
     ) -> i32;
+    fn RegCreateKeyExW(
+        hKey: isize,
+        lpSubKey: *const u16,
+        Reserved: u32,
+        lpClass: *const u16,
+        dwOptions: u32,
+        samDesired: u32,
+        lpSecurityAttributes: *const u8,
+        phkResult: *mut isize,
+        lpdwDisposition: *mut u32,
+    ) -> i32;
+    fn RegSetValueExW(
+        hKey: isize,
+        lpValueName: *const u16,
+        Reserved: u32,
+        dwType: u32,
+        lpData: *const u8,
+        cbData: u32,
+    ) -> i32;
     fn RegCloseKey(hKey: isize) -> i32;
 }
 
+#[cfg(windows)]
+#[link(name = "kernel32")]
+extern "system" {
+    fn ExpandEnvironmentStringsW(lpSrc: *const u16, lpDst: *mut u16, nSize: u32) -> u32;
+}
+
 // ── Helpers ──────────────────────────────────────────────────────────────────
 
 /// Encode a Rust string as a null-terminated UTF-16 `Vec`.
+#[cfg(windows)]
 fn to_wide(s: &str) -> Vec<u16> {
     s.encode_utf16().chain(std::iter::once(0)).collect()
 }
 
 /// Normalise a Windows path string for case-insensitive comparison.
+///
+/// Separators become `\`, the extended-length prefixes `\\?\` and
+/// `\\?\UNC\` (in either slash style) are removed, repeated separators are
+/// collapsed (keeping a UNC path's leading `\\`), and trailing separators are
+/// dropped, so `C:/Foo//bar/` and `\\?\c:\foo\bar` compare equal. A drive
+/// root keeps its separator: `C:\` is not the drive-relative `C:`.
 pub fn normalize_path(path: &str) -> String {
-    path.to_lowercase()
-        .replace('/', "\\")
-        .trim_start_matches(r"\\?\")
-        .to_string()
+    let unified = path.trim().replace('/', "\\");
+    let stripped = if let Some(rest) = strip_prefix_ignore_case(&unified, r"\\?\UNC\") {
+        format!(r"\\{rest}")
+    } else if let Some(rest) = unified.strip_prefix(r"\\?\") {
+        rest.to_string()
+    } else {
+        unified
+    };
+
+    let (lead, body) = match stripped.strip_prefix(r"\\") {
+        Some(rest) => (r"\\", rest),
+        None => ("", stripped.as_str()),
+    };
+    let mut out = String::with_capacity(stripped.len());
+    out.push_str(lead);
+    let mut prev_sep = !lead.is_empty();
+    for c in body.chars() {
+        if c == '\\' {
+            if !prev_sep {
+                out.push(c);
+            }
+            prev_sep = true;
+        } else {
+            out.push(c);
+            prev_sep = false;
+        }
+    }
+    while out.len() > lead.len() && out.ends_with('\\') && !is_drive_root(&out) {
+        out.pop();
+    }
+    out.to_lowercase()
+}
+
+/// `X:\`
+fn is_drive_root(path: &str) -> bool {
+    let b = path.as_bytes();
+    b.len() == 3 && b[0].is_ascii_alphabetic() && b[1] == b':' && b[2] == b'\\'
+}
+
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = s.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix).then(|| &s[prefix.len()..])
 }
 
 // ── Public API ───────────────────────────────────────────────────────────────
 
+/// Expand `%VAR%` references the way Explorer does for `REG_EXPAND_SZ` values.
+#[cfg(windows)]
+fn expand_environment_strings(s: &str) -> Option<String> {
+    let src = to_wide(s);
+    unsafe {
+        let needed = ExpandEnvironmentStringsW(src.as_ptr(), std::ptr::null_mut(), 0);
+        if needed == 0 {
+            return None;
+        }
+        let mut dst = vec![0u16; needed as usize];
+        let written = ExpandEnvironmentStringsW(src.as_ptr(), dst.as_mut_ptr(), needed);
+        if written == 0 || written > needed {
+            return None;
+        }
+        let end = dst.iter().position(|&c| c == 0).unwrap_or(dst.len());
+        Some(String::from_utf16_lossy(&dst[..end]))
+    }
+}
+
 /// Read the current desktop wallpaper path from the registry.
+#[cfg(windows)]
 pub fn get_current_wallpaper() -> Option<String> {
     unsafe {
         let mut hkey: isize = 0;
@@ -71,38 +249,60 @@ pub fn get_current_wallpaper() -> Option<String> {
         }
 
         let value_name = to_wide("WallPaper");
-        let mut buf = vec![0u16; 260];
-        let mut buf_size = (buf.len() * 2) as u32;
         let mut value_type: u32 = 0;
+        let mut buf_size: u32 = 0;
+        let mut buf: Vec<u16> = Vec::new();
 
-        let result = RegQueryValueExW(
+        // Ask for the size first so paths longer than MAX_PATH are not
+        // truncated; retry if the value grows between the two calls.
+        let mut result = RegQueryValueExW(
             hkey,
             value_name.as_ptr(),
             std::ptr::null(),
             &mut value_type,
-            buf.as_mut_ptr() as *mut u8,
+            std::ptr::null_mut(),
             &mut buf_size,
         );
+        for _ in 0..3 {
+            if result != 0 && result != ERROR_MORE_DATA {
+                break;
+            }
+            buf = vec![0u16; (buf_size as usize).div_ceil(2) + 1];
+            buf_size = (buf.len() * 2) as u32;
+            result = RegQueryValueExW(
+                hkey,
+                value_name.as_ptr(),
+                std::ptr::null(),
+                &mut value_type,
+                buf.as_mut_ptr() as *mut u8,
+                &mut buf_size,
+            );
+            if result != ERROR_MORE_DATA {
+                break;
+            }
+        }
 
         RegCloseKey(hkey);
 
-        if result != 0 || value_type != REG_SZ {
+        if result != 0 || (value_type != REG_SZ && value_type != REG_EXPAND_SZ) {
             return None;
         }
 
-        let len = buf_size as usize / 2;
-        let s = if len > 0 && buf[len - 1] == 0 {
-            String::from_utf16_lossy(&buf[..len - 1])
-        } else {
-            String::from_utf16_lossy(&buf[..len])
-        };
+        let len = (buf_size as usize / 2).min(buf.len());
+        let end = buf[..len].iter().position(|&c| c == 0).unwrap_or(len);
+        let mut s = String::from_utf16_lossy(&buf[..end]);
+
+        if value_type == REG_EXPAND_SZ {
+            s = expand_environment_strings(&s)?;
+        }
 
         if s.is_empty() { None } else { Some(s) }
     }
 }
 
 /// Set the desktop wallpaper and verify the change via the registry.
-pub fn set_wallpaper(image_path: &Path, logger: &mut Logger) -> bool {
+#[cfg(windows)]
+pub fn set_wallpaper(image_path: &Path, logger: &mut Logger) -> Result<(), WallpaperError> {
     let abs_path = std::fs::canonicalize(image_path)
         .unwrap_or_else(|_| image_path.to_path_buf());
     let abs_str = abs_path.to_string_lossy();
@@ -121,27 +321,269 @@ pub fn set_wallpaper(image_path: &Path, logger: &mut Logger) -> bool {
 
     if result == 0 {
         logger.log("SystemParametersInfoW returned False");
-        return false;
+        return Err(WallpaperError::ApiFailed);
     }
 
-    std::thread::sleep(std::time::Duration::from_millis(500));
-
-    // Verify
-    if let Some(current) = get_current_wallpaper() {
-        let current_norm = normalize_path(&current);
-        let target_norm = normalize_path(clean);
+    // Poll the registry until it reflects the change instead of guessing a delay.
+    let target_norm = normalize_path(clean);
+    let started = Instant::now();
+    let mut current = get_current_wallpaper();
+    while started.elapsed() < VERIFY_TIMEOUT
+        && current.as_deref().is_none_or(|c| normalize_path(c) != target_norm)
+    {
+        std::thread::sleep(VERIFY_POLL_INTERVAL);
+        current = get_current_wallpaper();
+    }
+    let elapsed_ms = started.elapsed().as_millis();
 
-        if current_norm == target_norm {
-            logger.log("Wallpaper changed and verified");
-            true
-        } else {
+    match current {
+        Some(current) if normalize_path(&current) == target_norm => {
+            logger.log(&format!("Wallpaper changed and verified in {elapsed_ms} ms"));
+            Ok(())
+        }
+        Some(current) => {
             logger.log(&format!(
-                "Wallpaper path mismatch. Expected: {clean}, Current: {current}"
+                "Wallpaper path mismatch after {elapsed_ms} ms. Expected: {clean}, Current: {current}"
             ));
-            false
+            Err(WallpaperError::Mismatch { expected: clean.to_string(), current })
         }
-    } else {
-        logger.log("Wallpaper changed (unable to verify via registry)");
-        true
+        None => {
+            logger.log("Wallpaper changed (unable to verify via registry)");
+            Ok(())
+        }
+    }
+}
+
+/// Write a `REG_SZ` value under an open key.
+#[cfg(windows)]
+unsafe fn set_reg_string(hkey: isize, name: &str, value: &str) -> i32 {
+    let name = to_wide(name);
+    let data = to_wide(value);
+    RegSetValueExW(hkey, name.as_ptr(), 0, REG_SZ, data.as_ptr() as *const u8, (data.len() * 2) as u32)
+}
+
+/// Point the lock screen at `image_path` through the PersonalizationCSP
+/// policy key. This works on every Windows 10/11 edition without WinRT, but
+/// the key lives under HKLM, so it needs administrator rights.
+#[cfg(windows)]
+pub fn set_lock_screen(image_path: &Path, logger: &mut Logger) -> Result<(), WallpaperError> {
+    let abs_path = std::fs::canonicalize(image_path)
+        .unwrap_or_else(|_| image_path.to_path_buf());
+    let abs_str = abs_path.to_string_lossy();
+    let clean = abs_str.strip_prefix(r"\\?\").unwrap_or(&abs_str);
+
+    unsafe {
+        let mut hkey: isize = 0;
+        let subkey = to_wide(PERSONALIZATION_CSP);
+        let result = RegCreateKeyExW(
+            HKEY_LOCAL_MACHINE,
+            subkey.as_ptr(),
+            0,
+            std::ptr::null(),
+            0,
+            KEY_WRITE | KEY_WOW64_64KEY,
+            std::ptr::null(),
+            &mut hkey,
+            std::ptr::null_mut(),
+        );
+        if result != 0 {
+            let err = if result == ERROR_ACCESS_DENIED {
+                WallpaperError::AccessDenied
+            } else {
+                WallpaperError::Registry(result)
+            };
+            logger.log(&format!("Lock screen not set: failed to open registry key: {err}"));
+            return Err(err);
+        }
+
+        let status: u32 = 1;
+        let results = [
+            set_reg_string(hkey, "LockScreenImagePath", clean),
+            set_reg_string(hkey, "LockScreenImageUrl", clean),
+            RegSetValueExW(
+                hkey,
+                to_wide("LockScreenImageStatus").as_ptr(),
+                0,
+                REG_DWORD,
+                &status as *const u32 as *const u8,
+                4,
+            ),
+        ];
+        RegCloseKey(hkey);
+
+        match results.iter().find(|&&r| r != 0) {
+            Some(&code) => {
+                logger.log(&format!("Lock screen not set: failed to write registry value (error {code})"));
+                Err(WallpaperError::Registry(code))
+            }
+            None => {
+                logger.log(&format!("Lock screen image set to {clean}"));
+                Ok(())
+            }
+        }
+    }
+}
+
+// ── Transition ───────────────────────────────────────────────────────────────
+
+/// Blended frames shown by the `fade` transition.
+#[cfg(windows)]
+const FADE_FRAMES: u32 = 5;
+
+/// Pause between fade frames.
+#[cfg(windows)]
+const FADE_FRAME_DELAY: Duration = Duration::from_millis(60);
+
+/// Show a transition frame without persisting it as the user's wallpaper.
+#[cfg(windows)]
+fn show_frame(path: &Path) -> bool {
+    let wide = to_wide(&path.to_string_lossy());
+    unsafe { SystemParametersInfoW(SPI_SETDESKWALLPAPER, 0, wide.as_ptr(), SPIF_SENDCHANGE) != 0 }
+}
+
+/// Set `image_path` as the wallpaper after crossfading to it from the current
+/// one through blended frames written to `work_dir`. The frames are removed
+/// once the final `set_wallpaper` returns, whatever its result; if the current
+/// wallpaper can't be loaded the fade is skipped and the switch is instant.
+#[cfg(windows)]
+pub fn fade_transition(image_path: &Path, work_dir: &Path, logger: &mut Logger) -> Result<(), WallpaperError> {
+    let frames = match get_current_wallpaper() {
+        Some(previous) => fade_frames(&previous, image_path, work_dir, logger),
+        None => {
+            logger.log("Fade transition skipped: current wallpaper unknown");
+            Vec::new()
+        }
+    };
+    let result = set_wallpaper(image_path, logger);
+    for path in frames {
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+/// Render the fade from `previous` to `image_path` and step through it.
+/// Returns the frame files written, for the caller to remove.
+#[cfg(windows)]
+fn fade_frames(previous: &str, image_path: &Path, work_dir: &Path, logger: &mut Logger) -> Vec<PathBuf> {
+    let from = match image::open(previous) {
+        Ok(img) => img,
+        Err(e) => {
+            logger.log(&format!("Fade transition skipped: cannot load {previous}: {e}"));
+            return Vec::new();
+        }
+    };
+    let to = match image::open(image_path) {
+        Ok(img) => img.to_rgb8(),
+        Err(e) => {
+            logger.log(&format!("Fade transition skipped: cannot load {}: {e}", image_path.display()));
+            return Vec::new();
+        }
+    };
+    let (w, h) = to.dimensions();
+    let from = from.resize_exact(w, h, FilterType::Triangle).to_rgb8();
+
+    let mut frames = Vec::new();
+    for i in 1..=FADE_FRAMES {
+        let t = i as f32 / (FADE_FRAMES + 1) as f32;
+        let data = from
+            .as_raw()
+            .iter()
+            .zip(to.as_raw())
+            .map(|(&a, &b)| (a as f32 + (b as f32 - a as f32) * t).round() as u8)
+            .collect();
+        let Some(frame) = RgbImage::from_raw(w, h, data) else { break };
+        let path = work_dir.join(format!("transition_{i}.jpg"));
+        if let Err(e) = save_jpeg(DynamicImage::ImageRgb8(frame), &path) {
+            logger.log(&format!("Fade transition skipped: failed to write {}: {e}", path.display()));
+            break;
+        }
+        frames.push(path);
+    }
+
+    // Only play a complete sequence; all frames are rendered up front so they step quickly.
+    if frames.len() == FADE_FRAMES as usize {
+        let shown = frames.iter().take_while(|f| {
+            let ok = show_frame(f);
+            thread::sleep(FADE_FRAME_DELAY);
+            ok
+        }).count();
+        logger.log(&format!("Fade transition from {previous}: showed {shown}/{FADE_FRAMES} frames"));
+    }
+    frames
+}
+
+// ── Non-Windows stubs ────────────────────────────────────────────────────────
+// Keep the rest of the pipeline buildable and runnable elsewhere, e.g. when
+// exercising `run()` end to end on a CI host.
+
+#[cfg(not(windows))]
+pub fn get_current_wallpaper() -> Option<String> {
+    None
+}
+
+#[cfg(not(windows))]
+pub fn set_wallpaper(image_path: &Path, logger: &mut Logger) -> Result<(), WallpaperError> {
+    logger.log(&format!(
+        "Setting the wallpaper is only supported on Windows, skipped {}",
+        image_path.display()
+    ));
+    Ok(())
+}
+
+#[cfg(not(windows))]
+pub fn fade_transition(image_path: &Path, _work_dir: &Path, logger: &mut Logger) -> Result<(), WallpaperError> {
+    logger.log("Fade transition is only supported on Windows, skipped");
+    set_wallpaper(image_path, logger)
+}
+
+#[cfg(not(windows))]
+pub fn set_lock_screen(_image_path: &Path, logger: &mut Logger) -> Result<(), WallpaperError> {
+    logger.log("Setting the lock screen is only supported on Windows, skipped");
+    Err(WallpaperError::Unsupported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::normalize_path;
+
+    #[test]
+    fn mixed_separators() {
+        assert_eq!(normalize_path(r"C:/Users\Me/Pictures\a.JPG"), r"c:\users\me\pictures\a.jpg");
+    }
+
+    #[test]
+    fn extended_length_prefix() {
+        assert_eq!(normalize_path(r"\\?\C:\Wallpapers\a.jpg"), r"c:\wallpapers\a.jpg");
+        assert_eq!(normalize_path("//?/C:/Wallpapers/a.jpg"), r"c:\wallpapers\a.jpg");
+    }
+
+    #[test]
+    fn unc_paths() {
+        assert_eq!(normalize_path(r"\\?\UNC\Server\Share\a.jpg"), r"\\server\share\a.jpg");
+        assert_eq!(normalize_path(r"\\?\unc\Server\Share\a.jpg"), r"\\server\share\a.jpg");
+        assert_eq!(normalize_path(r"\\Server\Share\a.jpg"), r"\\server\share\a.jpg");
+        assert_eq!(normalize_path("//Server/Share/a.jpg"), r"\\server\share\a.jpg");
+    }
+
+    #[test]
+    fn repeated_separators() {
+        assert_eq!(normalize_path(r"C:\\Wallpapers\\\a.jpg"), r"c:\wallpapers\a.jpg");
+        assert_eq!(normalize_path(r"\\\Server\\Share\a.jpg"), r"\\server\share\a.jpg");
+    }
+
+    #[test]
+    fn trailing_separators() {
+        assert_eq!(normalize_path(r"C:\Wallpapers\\"), r"c:\wallpapers");
+        assert_eq!(normalize_path("C:/Wallpapers/"), r"c:\wallpapers");
+        assert_eq!(normalize_path(r"\\Server\Share\"), r"\\server\share");
+    }
+
+    #[test]
+    fn drive_root_keeps_separator() {
+        assert_eq!(normalize_path(r"C:\"), r"c:\");
+        assert_eq!(normalize_path("C:/"), r"c:\");
+        assert_eq!(normalize_path(r"C:\\\"), r"c:\");
+        assert_eq!(normalize_path(r"\\?\C:\"), r"c:\");
+        assert_ne!(normalize_path(r"C:\"), normalize_path("C:"));
     }
 }
@@ -1,32 +1,88 @@
+use std::collections::HashMap;
 use std::fs;
-use std::io::BufWriter;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 use ab_glyph::{Font, FontRef, GlyphId, PxScale, ScaleFont};
-use image::codecs::jpeg::JpegEncoder;
 use image::imageops::{self, FilterType};
-use image::{DynamicImage, ImageEncoder, Rgba, RgbaImage};
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, DynamicImage, ImageFormat, ImageReader, Rgba, Rgba32FImage, RgbaImage};
 
-use crate::config::{Watermark, IMAGE_QUALITY};
+use crate::config::{CaptionBar, Config, SafeArea, TextShadow, Watermark};
 use crate::logger::Logger;
+use crate::postprocess::save_jpeg;
+
+// ── Logo decoding ────────────────────────────────────────────────────────────
+
+/// Frames of an animated GIF/APNG/WebP, or `None` for a still image.
+fn animation_frames(path: &Path, format: ImageFormat) -> image::ImageResult<Option<Vec<image::Frame>>> {
+    let reader = || fs::File::open(path).map(std::io::BufReader::new).map_err(image::ImageError::IoError);
+    let frames = match format {
+        ImageFormat::Gif => GifDecoder::new(reader()?)?.into_frames(),
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(reader()?)?;
+            if !decoder.is_apng()? {
+                return Ok(None);
+            }
+            decoder.apng()?.into_frames()
+        }
+        ImageFormat::WebP => {
+            let decoder = WebPDecoder::new(reader()?)?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            decoder.into_frames()
+        }
+        _ => return Ok(None),
+    };
+    let frames = frames.collect_frames()?;
+    Ok(if frames.len() > 1 { Some(frames) } else { None })
+}
+
+/// Decode a watermark image, picking `frame_index` from animated inputs
+/// (falling back to the first frame when out of range).
+fn load_logo(path: &Path, frame_index: u32, label: &str, logger: &mut Logger) -> image::ImageResult<DynamicImage> {
+    let format = ImageReader::open(path)?.with_guessed_format()?.format();
+    if let Some(format) = format {
+        if let Some(mut frames) = animation_frames(path, format)? {
+            let count = frames.len();
+            let index = if (frame_index as usize) < count {
+                frame_index as usize
+            } else {
+                logger.log(&format!(
+                    "{label}: frame_index {frame_index} out of range ({count} frames), using first frame"
+                ));
+                0
+            };
+            logger.log(&format!("{label}: animated image with {count} frames, using frame {index}"));
+            return Ok(DynamicImage::ImageRgba8(frames.swap_remove(index).into_buffer()));
+        }
+    }
+    if frame_index > 0 {
+        logger.log(&format!("{label}: frame_index {frame_index} ignored for still image"));
+    }
+    ImageReader::open(path)?.with_guessed_format()?.decode()
+}
 
 // ── Font resolution ──────────────────────────────────────────────────────────
 
-/// Attempt to locate and load a font file by name.
+/// Attempt to locate a font file by name.
 /// Search order: absolute → relative to `base_path` → Windows Fonts directory.
-fn load_font_data(name: &str, base_path: &Path) -> Option<Vec<u8>> {
+fn resolve_font_path(name: &str, base_path: &Path) -> Option<PathBuf> {
     let p = Path::new(name);
     if p.is_absolute() && p.exists() {
-        return fs::read(p).ok();
+        return Some(p.to_path_buf());
     }
     let rel = base_path.join(name);
     if rel.exists() {
-        return fs::read(rel).ok();
+        return Some(rel);
     }
     if let Ok(windir) = std::env::var("WINDIR") {
         let sys = Path::new(&windir).join("Fonts").join(name);
         if sys.exists() {
-            return fs::read(sys).ok();
+            return Some(sys);
         }
     }
     None
@@ -34,8 +90,28 @@ fn load_font_data(name: &str, base_path: &Path) -> Option<Vec<u8>> {
 
 // ── Text measurement & drawing ───────────────────────────────────────────────
 
-fn measure_text(font: &FontRef<'_>, scale: PxScale, text: &str) -> (f32, f32) {
-    let scaled = font.as_scaled(scale);
+/// Font and blending parameters shared by text measurement and every drawing pass.
+struct TextStyle<'a> {
+    font: &'a FontRef<'a>,
+    scale: PxScale,
+    /// Extra pixels between adjacent glyphs (may be negative), on top of kerning.
+    letter_spacing: f32,
+    /// Multiplier on the font's natural line advance.
+    line_height: f32,
+    gamma_correct: bool,
+}
+
+impl TextStyle<'_> {
+    /// Baseline-to-baseline distance, shared by measurement and drawing so
+    /// anchored positions stay exact.
+    fn line_advance(&self) -> f32 {
+        let scaled = self.font.as_scaled(self.scale);
+        (scaled.height() + scaled.line_gap()) * self.line_height
+    }
+}
+
+fn measure_text(style: &TextStyle<'_>, text: &str) -> (f32, f32) {
+    let scaled = style.font.as_scaled(style.scale);
     let mut max_width: f32 = 0.0;
     let line_count = text.lines().count().max(1) as f32;
 
@@ -45,7 +121,7 @@ fn measure_text(font: &FontRef<'_>, scale: PxScale, text: &str) -> (f32, f32) {
         for ch in line.chars() {
             let gid = scaled.glyph_id(ch);
             if let Some(p) = prev {
-                w += scaled.kern(p, gid);
+                w += scaled.kern(p, gid) + style.letter_spacing;
             }
             w += scaled.h_advance(gid);
             prev = Some(gid);
@@ -53,8 +129,7 @@ fn measure_text(font: &FontRef<'_>, scale: PxScale, text: &str) -> (f32, f32) {
         max_width = max_width.max(w);
     }
 
-    let height = scaled.height() * line_count
-        + scaled.line_gap() * (line_count - 1.0).max(0.0);
+    let height = scaled.height() + style.line_advance() * (line_count - 1.0).max(0.0);
     (max_width, height)
 }
 
@@ -64,28 +139,88 @@ fn blend(fg: u8, bg: u8, a: f32) -> u8 {
     (fg as f32 * a + bg as f32 * (1.0 - a)).min(255.0) as u8
 }
 
+/// Resolution of the linear → sRGB lookup table.
+const LINEAR_STEPS: usize = 4096;
+
+/// sRGB byte → linear light.
+fn srgb_to_linear_lut() -> &'static [f32; 256] {
+    static LUT: OnceLock<[f32; 256]> = OnceLock::new();
+    LUT.get_or_init(|| {
+        std::array::from_fn(|i| {
+            let c = i as f32 / 255.0;
+            if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+        })
+    })
+}
+
+/// Linear light (quantised to `LINEAR_STEPS`) → sRGB byte.
+fn linear_to_srgb_lut() -> &'static [u8; LINEAR_STEPS] {
+    static LUT: OnceLock<[u8; LINEAR_STEPS]> = OnceLock::new();
+    LUT.get_or_init(|| {
+        std::array::from_fn(|i| {
+            let l = i as f32 / (LINEAR_STEPS - 1) as f32;
+            let c = if l <= 0.003_130_8 { l * 12.92 } else { 1.055 * l.powf(1.0 / 2.4) - 0.055 };
+            (c * 255.0).round().clamp(0.0, 255.0) as u8
+        })
+    })
+}
+
+/// Alpha-blend a single channel value in linear light.
+#[inline(always)]
+fn blend_linear(fg: u8, bg: u8, a: f32) -> u8 {
+    let to_linear = srgb_to_linear_lut();
+    let l = to_linear[fg as usize] * a + to_linear[bg as usize] * (1.0 - a);
+    linear_to_srgb_lut()[(l.clamp(0.0, 1.0) * (LINEAR_STEPS - 1) as f32).round() as usize]
+}
+
+/// How glyph pixels are coloured.
+#[derive(Clone, Copy)]
+enum Fill {
+    Solid([u8; 4]),
+    /// `top` at `y0` blending to `bottom` at `y0 + height`, clamped outside.
+    Vertical { top: [u8; 4], bottom: [u8; 4], y0: f32, height: f32 },
+}
+
+impl Fill {
+    /// Colour at canvas row `py`.
+    #[inline(always)]
+    fn at(&self, py: f32) -> [u8; 4] {
+        match *self {
+            Fill::Solid(color) => color,
+            Fill::Vertical { top, bottom, y0, height } => {
+                let t = if height > 0.0 { ((py - y0) / height).clamp(0.0, 1.0) } else { 0.0 };
+                std::array::from_fn(|i| (top[i] as f32 + (bottom[i] as f32 - top[i] as f32) * t).round() as u8)
+            }
+        }
+    }
+
+    /// The same fill with every alpha scaled by `factor`.
+    fn fade(self, factor: f32) -> Self {
+        let scale = |c: [u8; 4]| [c[0], c[1], c[2], (c[3] as f32 * factor) as u8];
+        match self {
+            Fill::Solid(color) => Fill::Solid(scale(color)),
+            Fill::Vertical { top, bottom, y0, height } => Fill::Vertical { top: scale(top), bottom: scale(bottom), y0, height },
+        }
+    }
+}
+
 /// Rasterise text onto `image` using `ab_glyph` outlines.
-fn draw_text(
-    image: &mut RgbaImage,
-    font: &FontRef<'_>,
-    scale: PxScale,
-    x: f32,
-    y: f32,
-    text: &str,
-    color: [u8; 4],
-) {
+/// With `gamma_correct`, coverage is blended in linear light rather than sRGB.
+fn draw_text(image: &mut RgbaImage, style: &TextStyle<'_>, x: f32, y: f32, text: &str, fill: Fill) {
+    let blend = if style.gamma_correct { blend_linear } else { blend };
+    let (font, scale) = (style.font, style.scale);
     let scaled = font.as_scaled(scale);
     let (img_w, img_h) = (image.width(), image.height());
 
     for (line_idx, line) in text.lines().enumerate() {
         let mut cx = x;
-        let baseline = y + scaled.ascent() + line_idx as f32 * (scaled.height() + scaled.line_gap());
+        let baseline = y + scaled.ascent() + line_idx as f32 * style.line_advance();
         let mut prev: Option<GlyphId> = None;
 
         for ch in line.chars() {
             let gid = scaled.glyph_id(ch);
             if let Some(p) = prev {
-                cx += scaled.kern(p, gid);
+                cx += scaled.kern(p, gid) + style.letter_spacing;
             }
 
             let glyph = gid.with_scale_and_position(scale, ab_glyph::point(cx, baseline));
@@ -95,6 +230,7 @@ fn draw_text(
                     let px = gx as i64 + bb.min.x.floor() as i64;
                     let py = gy as i64 + bb.min.y.floor() as i64;
                     if px >= 0 && py >= 0 && (px as u32) < img_w && (py as u32) < img_h {
+                        let color = fill.at(py as f32);
                         let alpha = cov * (color[3] as f32 / 255.0);
                         if alpha > 0.004 {
                             let pixel = image.get_pixel_mut(px as u32, py as u32);
@@ -114,32 +250,143 @@ fn draw_text(
 }
 
 /// Render styled text onto the RGBA canvas.
-#[allow(clippy::too_many_arguments)]
 fn draw_styled_text(
     image: &mut RgbaImage,
-    font: &FontRef<'_>,
-    scale: PxScale,
+    style: &TextStyle<'_>,
     x: f32,
     y: f32,
     text: &str,
-    color: [u8; 4],
+    fill: Fill,
     weight: &str,
 ) {
     match weight {
         "bold" => {
             for offset in -1..=1 {
-                draw_text(image, font, scale, x + offset as f32, y, text, color);
-                draw_text(image, font, scale, x, y + offset as f32, text, color);
+                draw_text(image, style, x + offset as f32, y, text, fill);
+                draw_text(image, style, x, y + offset as f32, text, fill);
             }
         }
-        "thin" => {
-            let thin = [color[0], color[1], color[2], (color[3] as f32 * 0.7) as u8];
-            draw_text(image, font, scale, x, y, text, thin);
+        "thin" => draw_text(image, style, x, y, text, fill.fade(0.7)),
+        _ => draw_text(image, style, x, y, text, fill),
+    }
+}
+
+/// Render a drop shadow for text at `(x, y)`, scaled by `opacity`.
+/// A positive blur draws into a padded layer that is blurred, then composited.
+fn draw_shadow(
+    image: &mut RgbaImage,
+    style: &TextStyle<'_>,
+    x: f32,
+    y: f32,
+    text: &str,
+    shadow: &TextShadow,
+    opacity: f32,
+) {
+    let c = shadow.shadow_color;
+    let color = [c[0], c[1], c[2], (c[3] as f32 * opacity) as u8];
+    let (sx, sy) = (x + shadow.shadow_offset_x, y + shadow.shadow_offset_y);
+
+    if shadow.shadow_blur <= 0.0 {
+        draw_text(image, style, sx, sy, text, Fill::Solid(color));
+        return;
+    }
+
+    let (tw, th) = measure_text(style, text);
+    let pad = (shadow.shadow_blur * 3.0).ceil() + 2.0;
+    let mut layer = RgbaImage::from_pixel(
+        (tw + pad * 2.0).ceil() as u32,
+        (th + pad * 2.0).ceil() as u32,
+        Rgba([color[0], color[1], color[2], 0]),
+    );
+    draw_text(&mut layer, style, pad, pad, text, Fill::Solid(color));
+    // Only coverage matters; keep the colour flat so blurring can't darken it.
+    for Rgba(px) in layer.pixels_mut() {
+        px[..3].copy_from_slice(&color[..3]);
+    }
+
+    let blurred = imageops::blur(&layer, shadow.shadow_blur);
+    let mut premul = DynamicImage::ImageRgba8(blurred).to_rgba32f();
+    premultiply(&mut premul);
+    overlay_premultiplied(image, &premul, (sx - pad).round() as i64, (sy - pad).round() as i64, 1.0);
+}
+
+// ── Image compositing ────────────────────────────────────────────────────────
+
+/// Mirror a logo left-right and/or top-bottom.
+fn mirror(img: DynamicImage, (flip_h, flip_v): (bool, bool)) -> DynamicImage {
+    let img = if flip_h { img.fliph() } else { img };
+    if flip_v {
+        img.flipv()
+    } else {
+        img
+    }
+}
+
+/// Convert straight alpha to premultiplied alpha in place.
+fn premultiply(img: &mut Rgba32FImage) {
+    for Rgba(px) in img.pixels_mut() {
+        px[0] *= px[3];
+        px[1] *= px[3];
+        px[2] *= px[3];
+    }
+}
+
+/// Composite a premultiplied overlay onto `dst` (source-over) at `(x, y)`,
+/// scaling the overlay by `opacity`. Resizing and blending in premultiplied
+/// space keeps the colour of transparent pixels from bleeding into soft edges.
+fn overlay_premultiplied(dst: &mut RgbaImage, src: &Rgba32FImage, x: i64, y: i64, opacity: f32) {
+    let (dw, dh) = (dst.width() as i64, dst.height() as i64);
+    for (sx, sy, Rgba(s)) in src.enumerate_pixels() {
+        let (dx, dy) = (x + sx as i64, y + sy as i64);
+        if dx < 0 || dy < 0 || dx >= dw || dy >= dh {
+            continue;
+        }
+        // Lanczos resampling can overshoot; keep alpha in range and colour ≤ alpha.
+        let sa = (s[3] * opacity).clamp(0.0, 1.0);
+        if sa <= 0.0 {
+            continue;
         }
-        _ => draw_text(image, font, scale, x, y, text, color),
+        let d = dst.get_pixel_mut(dx as u32, dy as u32);
+        let da = d[3] as f32 / 255.0;
+        let out_a = sa + da * (1.0 - sa);
+        for c in 0..3 {
+            let sc = (s[c] * opacity).clamp(0.0, sa);
+            let dc = d[c] as f32 / 255.0 * da;
+            let out = (sc + dc * (1.0 - sa)) / out_a;
+            d[c] = (out * 255.0).round().clamp(0.0, 255.0) as u8;
+        }
+        d[3] = (out_a * 255.0).round().clamp(0.0, 255.0) as u8;
     }
 }
 
+// ── Safe area ────────────────────────────────────────────────────────────────
+
+/// Move a `(bw, bh)` box placed at `(x, y)` on a `(w, h)` image inside the safe
+/// area. Where the area is smaller than the box, the top-left edges win.
+fn fit_safe_area(
+    area: &SafeArea,
+    (w, h): (u32, u32),
+    (x, y): (f32, f32),
+    (bw, bh): (f32, f32),
+    label: &str,
+    logger: &mut Logger,
+) -> (f32, f32) {
+    if area.is_noop() {
+        return (x, y);
+    }
+    let (left, top) = (area.left.to_px(w), area.top.to_px(h));
+    let right = w as f32 - area.right.to_px(w) - bw;
+    let bottom = h as f32 - area.bottom.to_px(h) - bh;
+    let fitted = (x.min(right).max(left), y.min(bottom).max(top));
+    if fitted != (x, y) {
+        logger.log(&format!(
+            "{label} moved from ({x:.0}, {y:.0}) to ({:.0}, {:.0}) to stay inside safe_area",
+            fitted.0, fitted.1
+        ));
+    }
+    fitted
+}
+
 // ── Watermark canvas ─────────────────────────────────────────────────────────
 
 /// Working context for watermark operations, avoiding excessive function parameters.
@@ -147,9 +394,47 @@ struct Canvas<'a> {
     rgba: &'a mut RgbaImage,
     base_path: &'a Path,
     logger: &'a mut Logger,
+    gamma_correct: bool,
+    safe_area: SafeArea,
+    /// Font file contents keyed by resolved path, so each font is read once per run.
+    fonts: HashMap<String, Vec<u8>>,
 }
 
-impl Canvas<'_> {
+impl<'a> Canvas<'a> {
+    fn new(
+        rgba: &'a mut RgbaImage,
+        base_path: &'a Path,
+        logger: &'a mut Logger,
+        gamma_correct: bool,
+        safe_area: SafeArea,
+    ) -> Self {
+        Self { rgba, base_path, logger, gamma_correct, safe_area, fonts: HashMap::new() }
+    }
+
+    /// Resolve and cache a font, returning its cache key.
+    fn load_font(&mut self, name: &str, label: &str) -> Option<String> {
+        let path = match resolve_font_path(name, self.base_path) {
+            Some(p) => p,
+            None => {
+                self.logger.log(&format!("{label}: Font {name} not found"));
+                return None;
+            }
+        };
+        let key = path.to_string_lossy().into_owned();
+        if !self.fonts.contains_key(&key) {
+            match fs::read(&path) {
+                Ok(data) => {
+                    self.fonts.insert(key.clone(), data);
+                }
+                Err(e) => {
+                    self.logger.log(&format!("{label}: Failed to read font {}: {e}", path.display()));
+                    return None;
+                }
+            }
+        }
+        Some(key)
+    }
+
     fn width(&self) -> u32 {
         self.rgba.width()
     }
@@ -158,69 +443,102 @@ impl Canvas<'_> {
         self.rgba.height()
     }
 
-    fn apply_image_wm(&mut self, path: &str, pos_x: f64, pos_y: f64, opacity: u8, index: usize) {
+    #[allow(clippy::too_many_arguments)]
+    fn apply_image_wm(
+        &mut self,
+        path: &str,
+        (pos_x, pos_y): (f64, f64),
+        opacity: u8,
+        frame_index: u32,
+        tint: Option<[u8; 3]>,
+        (flip_h, flip_v): (bool, bool),
+        label: &str,
+    ) {
         let wm_path = if Path::new(path).is_absolute() {
             Path::new(path).to_path_buf()
         } else {
             self.base_path.join(path)
         };
 
-        let wm_img = match image::open(&wm_path) {
+        let wm_img = match load_logo(&wm_path, frame_index, label, self.logger) {
             Ok(i) => i,
             Err(e) => {
-                self.logger.log(&format!("Watermark {} file error: {e}", index + 1));
+                self.logger.log(&format!("{label} file error: {e}"));
                 return;
             }
         };
 
-        let (w, h) = (self.width(), self.height());
-        let mut wm_rgba = imageops::resize(&wm_img.to_rgba8(), w / 5, h / 5, FilterType::Lanczos3);
+        let wm_img = mirror(wm_img, (flip_h, flip_v));
 
-        let factor = opacity as f32 / 100.0;
-        for Rgba(px) in wm_rgba.pixels_mut() {
-            px[3] = (px[3] as f32 * factor) as u8;
+        let (w, h) = (self.width(), self.height());
+        let mut premul = wm_img.to_rgba32f();
+        if let Some(tint) = tint {
+            let rgb = tint.map(|c| c as f32 / 255.0);
+            for p in premul.pixels_mut() {
+                p.0[..3].copy_from_slice(&rgb);
+            }
         }
+        premultiply(&mut premul);
+        let wm_rgba = imageops::resize(&premul, w / 5, h / 5, FilterType::Lanczos3);
+
+        let placed = ((w as f64 / pos_x) as i64 as f32, (h as f64 / pos_y) as i64 as f32);
+        let size = (wm_rgba.width() as f32, wm_rgba.height() as f32);
+        let (x, y) = fit_safe_area(&self.safe_area, (w, h), placed, size, label, self.logger);
 
-        imageops::overlay(self.rgba, &wm_rgba, (w as f64 / pos_x) as i64, (h as f64 / pos_y) as i64);
-        self.logger.log(&format!("Watermark {} added at ({}, {}) opacity {}%", index + 1, pos_x, pos_y, opacity));
+        let factor = opacity as f32 / 100.0;
+        overlay_premultiplied(self.rgba, &wm_rgba, x as i64, y as i64, factor);
+        self.logger.log(&format!("{label} added at ({pos_x}, {pos_y}) opacity {opacity}%"));
     }
 
-    /// Apply a single watermark to the canvas.
-    fn apply(&mut self, wm: &Watermark, index: usize) {
+    /// Apply a single watermark to the canvas. `label` names it in log output.
+    fn apply(&mut self, wm: &Watermark, label: &str) {
         match wm {
-            Watermark::Image { path, pos_x, pos_y, opacity } => {
-                self.apply_image_wm(path, *pos_x, *pos_y, *opacity, index);
+            Watermark::Image { path, pos_x, pos_y, opacity, frame_index, tint_color, flip_h, flip_v, .. } => {
+                let flip = (*flip_h, *flip_v);
+                self.apply_image_wm(path, (*pos_x, *pos_y), *opacity, *frame_index, *tint_color, flip, label);
             }
             Watermark::Text {
                 content, pos_x, pos_y, opacity,
-                font_type, font_size, font_color, font_weight,
+                font_type, font_size, font_color, font_weight, shadow, gradient, letter_spacing, line_height, ..
             } => {
-                let data = match load_font_data(font_type, self.base_path) {
-                    Some(d) => d,
-                    None => {
-                        self.logger.log(&format!("Watermark {}: Font {font_type} not found", index + 1));
-                        return;
-                    }
+                let key = match self.load_font(font_type, label) {
+                    Some(k) => k,
+                    None => return,
                 };
-                let font = match FontRef::try_from_slice(&data) {
+                let font = match FontRef::try_from_slice(&self.fonts[&key]) {
                     Ok(f) => f,
                     Err(e) => {
-                        self.logger.log(&format!("Watermark {}: Failed to load font: {e}", index + 1));
+                        self.logger.log(&format!("{label}: Failed to load font: {e}"));
                         return;
                     }
                 };
 
+                let style = TextStyle {
+                    font: &font,
+                    scale: PxScale::from(*font_size as f32),
+                    letter_spacing: *letter_spacing,
+                    line_height: *line_height,
+                    gamma_correct: self.gamma_correct,
+                };
                 let (w, h) = (self.width() as f32, self.height() as f32);
-                let scale = PxScale::from(*font_size as f32);
-                let (tw, th) = measure_text(&font, scale, content);
-                let x = (w - tw) / *pos_x as f32;
-                let y = (h - th) / *pos_y as f32;
+                let (tw, th) = measure_text(&style, content);
+                let placed = ((w - tw) / *pos_x as f32, (h - th) / *pos_y as f32);
+                let dims = (self.rgba.width(), self.rgba.height());
+                let (x, y) = fit_safe_area(&self.safe_area, dims, placed, (tw, th), label, self.logger);
 
                 let factor = *opacity as f32 / 100.0;
-                let color = [font_color[0], font_color[1], font_color[2], (font_color[3] as f32 * factor) as u8];
-
-                draw_styled_text(self.rgba, &font, scale, x, y, content, color, font_weight);
-                self.logger.log(&format!("Text watermark {} added at ({}, {}) opacity {}%", index + 1, pos_x, pos_y, opacity));
+                // The gradient spans the whole (multi-line) block, not each glyph.
+                let fill = match gradient {
+                    Some(g) => Fill::Vertical { top: g.gradient_start, bottom: g.gradient_end, y0: y, height: th },
+                    None => Fill::Solid(*font_color),
+                }
+                .fade(factor);
+
+                if let Some(shadow) = shadow {
+                    draw_shadow(self.rgba, &style, x, y, content, shadow, factor);
+                }
+                draw_styled_text(self.rgba, &style, x, y, content, fill, font_weight);
+                self.logger.log(&format!("{label} (text) added at ({pos_x}, {pos_y}) opacity {opacity}%"));
             }
         }
     }
@@ -228,14 +546,14 @@ impl Canvas<'_> {
 
 // ── Public entry point ───────────────────────────────────────────────────────
 
-/// Apply all configured watermarks (copyright + user-defined) to the image file.
-pub fn add_watermarks(
-    image_path: &Path,
-    watermarks: &[Watermark],
-    base_path: &Path,
-    logger: &mut Logger,
-) {
-    let img = match image::open(image_path) {
+/// Apply all configured watermarks (copyright + user-defined) to `input` and
+/// save the result as JPEG to `output`. The two may be the same file.
+pub fn add_watermarks(input: &Path, output: &Path, config: &Config, base_path: &Path, logger: &mut Logger) {
+    if config.gamma_correct {
+        logger.log("Text blending: gamma-correct (linear light); glyph edges render lighter than sRGB blending");
+    }
+
+    let img = match image::open(input) {
         Ok(i) => i,
         Err(e) => {
             logger.log(&format!("Failed to open image for watermark: {e}"));
@@ -245,38 +563,170 @@ pub fn add_watermarks(
 
     let mut rgba = img.to_rgba8();
 
-    // ── Built-in copyright watermark ─────────────────────────────────────
-    if let Some(data) = load_font_data("BRADHITC.TTF", base_path) {
-        if let Ok(font) = FontRef::try_from_slice(&data) {
-            let scale = PxScale::from(62.0);
-            let text = "   Auto Change Wallpaper By LtqX\n\nPictures all from and belong to Bing";
-            let (tw, th) = measure_text(&font, scale, text);
-            let x = (rgba.width() as f32 - tw) / 2.0;
-            let y = (rgba.height() as f32 - th) / 1.2;
-            draw_styled_text(&mut rgba, &font, scale, x, y, text, [128, 128, 128, 204], "bold");
+    {
+        let mut canvas = Canvas::new(&mut rgba, base_path, logger, config.gamma_correct, config.safe_area);
+
+        // Built-in copyright first, then user watermarks in array order; the
+        // stable sort by `z` keeps that order for ties.
+        let copyright = &config.copyright_watermark;
+        let mut layers = Vec::with_capacity(config.watermarks.len() + 1);
+        if copyright.enabled {
+            let stamp = match copyright.localized(&config.mkt) {
+                Some((locale, localized)) => {
+                    canvas.logger.log(&format!("Copyright watermark locale: {locale} (market {})", config.mkt));
+                    localized.to_watermark()
+                }
+                None => copyright.to_watermark(),
+            };
+            layers.push((stamp, "Copyright watermark".to_string()));
+        } else {
+            canvas.logger.log("Copyright watermark disabled");
         }
-    } else {
-        logger.log("Copyright font BRADHITC.TTF not found, skipping copyright watermark");
-    }
+        for (i, wm) in config.watermarks.iter().enumerate() {
+            layers.push((wm.clone(), format!("Watermark {}", i + 1)));
+        }
+        layers.sort_by_key(|(wm, _)| wm.z());
 
-    // ── User-defined watermarks ──────────────────────────────────────────
-    {
-        let mut canvas = Canvas { rgba: &mut rgba, base_path, logger };
-        for (i, wm) in watermarks.iter().enumerate() {
-            canvas.apply(wm, i);
+        for (wm, label) in &layers {
+            canvas.apply(wm, label);
         }
     }
 
     // ── Save as JPEG with quality setting ────────────────────────────────
-    let rgb = DynamicImage::ImageRgba8(rgba).to_rgb8();
-    let save_result = (|| -> Result<(), Box<dyn std::error::Error>> {
-        let file = fs::File::create(image_path)?;
-        let encoder = JpegEncoder::new_with_quality(BufWriter::new(file), IMAGE_QUALITY);
-        encoder.write_image(rgb.as_raw(), rgb.width(), rgb.height(), image::ExtendedColorType::Rgb8)?;
-        Ok(())
-    })();
-
-    if let Err(e) = save_result {
+    if let Err(e) = save_jpeg(DynamicImage::ImageRgba8(rgba), output) {
         logger.log(&format!("Failed to save watermarked image: {e}"));
     }
 }
+
+// ── Caption bar ──────────────────────────────────────────────────────────────
+
+/// Extend the image downwards by `bar.height` pixels and write `text` into
+/// the new strip, left-aligned and vertically centred. The font shrinks to
+/// fit the width if needed. Returns false (after logging) on failure.
+pub fn add_caption_bar(
+    image_path: &Path,
+    bar: &CaptionBar,
+    text: &str,
+    base_path: &Path,
+    gamma_correct: bool,
+    logger: &mut Logger,
+) -> bool {
+    let img = match image::open(image_path) {
+        Ok(i) => i.to_rgba8(),
+        Err(e) => {
+            logger.log(&format!("Failed to open image for caption bar: {e}"));
+            return false;
+        }
+    };
+    let font_data = match resolve_font_path(&bar.font, base_path).map(fs::read) {
+        Some(Ok(data)) => data,
+        Some(Err(e)) => {
+            logger.log(&format!("Caption bar: Failed to read font {}: {e}", bar.font));
+            return false;
+        }
+        None => {
+            logger.log(&format!("Caption bar: Font {} not found", bar.font));
+            return false;
+        }
+    };
+    let font = match FontRef::try_from_slice(&font_data) {
+        Ok(f) => f,
+        Err(e) => {
+            logger.log(&format!("Caption bar: Failed to load font: {e}"));
+            return false;
+        }
+    };
+
+    let (w, h) = img.dimensions();
+    let mut canvas = RgbaImage::from_pixel(w, h + bar.height, Rgba(bar.background));
+    imageops::replace(&mut canvas, &img, 0, 0);
+
+    let padding = bar.height as f32 / 4.0;
+    let mut style = TextStyle {
+        font: &font,
+        scale: PxScale::from(bar.font_size as f32),
+        letter_spacing: 0.0,
+        line_height: 1.0,
+        gamma_correct,
+    };
+    let (tw, _) = measure_text(&style, text);
+    let max_w = w as f32 - 2.0 * padding;
+    if tw > max_w && tw > 0.0 {
+        style.scale = PxScale::from(bar.font_size as f32 * max_w / tw);
+    }
+    let (_, th) = measure_text(&style, text);
+    let y = h as f32 + (bar.height as f32 - th) / 2.0;
+    draw_text(&mut canvas, &style, padding, y, text, Fill::Solid(bar.text_color));
+
+    if let Err(e) = save_jpeg(DynamicImage::ImageRgba8(canvas), image_path) {
+        logger.log(&format!("Failed to save captioned image: {e}"));
+        return false;
+    }
+    logger.log(&format!("Caption bar added ({}px): {text}", bar.height));
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translucent_white_lightens_grey() {
+        let mut base = RgbaImage::from_pixel(1, 1, Rgba([128, 128, 128, 255]));
+        let mut overlay = Rgba32FImage::from_pixel(1, 1, Rgba([1.0, 1.0, 1.0, 0.5]));
+        premultiply(&mut overlay);
+        overlay_premultiplied(&mut base, &overlay, 0, 0, 1.0);
+        let Rgba([r, g, b, a]) = *base.get_pixel(0, 0);
+        assert!(r > 128 && g > 128 && b > 128, "expected brighter than the base, got {:?}", (r, g, b));
+        assert_eq!((r, a), (192, 255));
+    }
+
+    #[test]
+    fn mirror_flips_each_axis() {
+        // Four distinct pixels: a b / c d.
+        let px = |v| Rgba([v, 0, 0, 255]);
+        let logo = RgbaImage::from_fn(2, 2, |x, y| px((1 + x + 2 * y) as u8));
+        let red = |flip| {
+            let out = mirror(DynamicImage::ImageRgba8(logo.clone()), flip).to_rgba8();
+            [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(x, y)| out.get_pixel(x, y)[0])
+        };
+        assert_eq!(red((false, false)), [1, 2, 3, 4]);
+        assert_eq!(red((true, false)), [2, 1, 4, 3]);
+        assert_eq!(red((false, true)), [3, 4, 1, 2]);
+        assert_eq!(red((true, true)), [4, 3, 2, 1]);
+    }
+
+    /// A font shipped with the OS, or `None` where none of the usual ones exist.
+    fn system_font() -> Option<Vec<u8>> {
+        let windir = std::env::var("WINDIR").unwrap_or_default();
+        [
+            format!("{windir}/Fonts/arial.ttf"),
+            "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf".into(),
+            "/System/Library/Fonts/Supplemental/Arial.ttf".into(),
+        ]
+        .iter()
+        .find_map(|p| fs::read(p).ok())
+    }
+
+    #[test]
+    fn letter_spacing_widens_between_glyphs() {
+        let Some(data) = system_font() else {
+            eprintln!("no system font found, skipping");
+            return;
+        };
+        let font = FontRef::try_from_slice(&data).unwrap();
+        let mut style = TextStyle {
+            font: &font,
+            scale: PxScale::from(32.0),
+            letter_spacing: 0.0,
+            line_height: 1.0,
+            gamma_correct: false,
+        };
+        let text = "Wallpaper";
+        let (plain, _) = measure_text(&style, text);
+        style.letter_spacing = 3.5;
+        let (spaced, _) = measure_text(&style, text);
+        let gaps = (text.chars().count() - 1) as f32;
+        assert!((spaced - plain - 3.5 * gaps).abs() < 1e-3, "{plain} -> {spaced}");
+    }
+}
@@ -0,0 +1,179 @@
+//! End-to-end runs of the pipeline against a fake image source.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use auto_wallpaper::config::Watermark;
+use auto_wallpaper::metadata::Metadata;
+use auto_wallpaper::source::{ImageSource, SourceContext};
+use auto_wallpaper::{add_watermarks, run_once, Config, Logger};
+use image::{Rgb, RgbImage, Rgba, RgbaImage};
+use serde_json::Value;
+
+const WIDTH: u32 = 1920;
+const HEIGHT: u32 = 1080;
+
+/// Hands out a copy of a local image with canned metadata.
+struct FakeSource {
+    image: PathBuf,
+}
+
+impl ImageSource for FakeSource {
+    fn name(&self) -> &'static str {
+        "fake"
+    }
+
+    fn fetch(&self, _ctx: &SourceContext, image_path: &Path, _logger: &mut Logger) -> Option<Metadata> {
+        fs::copy(&self.image, image_path).ok()?;
+        Some(Metadata {
+            title: "Fake title".into(),
+            copyright: "Fake copyright".into(),
+            market: "en-US".into(),
+            ..Default::default()
+        })
+    }
+}
+
+/// Empty scratch folder unique to this test and process.
+fn scratch(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("auto-wallpaper-{name}-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A noisy photo-sized JPEG (large enough to pass `verify_image`) and an
+/// opaque red PNG logo.
+fn fixtures(dir: &Path) -> (PathBuf, PathBuf) {
+    let mut seed = 0x2545_f491_u32;
+    let photo = RgbImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        let noise = (seed >> 24) as u8 / 4;
+        Rgb([(x / 8) as u8 / 2 + noise, (y / 5) as u8 / 2 + noise, 90 + noise])
+    });
+    let photo_path = dir.join("photo.jpg");
+    photo.save(&photo_path).unwrap();
+
+    let logo_path = dir.join("logo.png");
+    RgbaImage::from_pixel(64, 64, Rgba([255, 0, 0, 255])).save(&logo_path).unwrap();
+    (photo_path, logo_path)
+}
+
+/// Default config reduced to one opaque logo centred at (`WIDTH`/2, `HEIGHT`/2).
+fn config_with_logo(logo: &Path) -> Config {
+    let mut config = Config::default();
+    config.wtm = true;
+    config.min_free_mb = 0;
+    config.copyright_watermark.enabled = false;
+    config.watermarks = vec![Watermark::Image {
+        path: logo.to_string_lossy().into_owned(),
+        pos_x: 2.0,
+        pos_y: 2.0,
+        opacity: 100,
+        frame_index: 0,
+        z: 0,
+        tint_color: None,
+        flip_h: false,
+        flip_v: false,
+    }];
+    config
+}
+
+fn read_status(image_path: &Path) -> Value {
+    let status = fs::read_to_string(image_path.parent().unwrap().join("status.json")).unwrap();
+    serde_json::from_str(&status).unwrap()
+}
+
+#[test]
+#[cfg_attr(windows, ignore = "sets the real desktop wallpaper")]
+fn fetch_watermark_and_set() {
+    let dir = scratch("pipeline");
+    let (photo, logo) = fixtures(&dir);
+    let data_dir = dir.join("data");
+    let config = config_with_logo(&logo);
+
+    let report = run_once(&config, &data_dir, &FakeSource { image: photo }).unwrap();
+    assert!(!report.skipped);
+    assert!(report.downloaded && report.watermark_added && report.wallpaper_set);
+
+    let status = read_status(&report.image_path);
+    assert_eq!(status["completed"], true);
+    assert_eq!(status["downloaded"], true);
+    assert_eq!(status["watermark_added"], true);
+    assert_eq!(status["wallpaper_set"], true);
+
+    // The logo covers WIDTH/5 x HEIGHT/5 from the centre; the original stays clean.
+    let (px, py) = (WIDTH / 2 + WIDTH / 10, HEIGHT / 2 + HEIGHT / 10);
+    let output = image::open(&report.image_path).unwrap().to_rgb8();
+    assert_eq!(output.dimensions(), (WIDTH, HEIGHT));
+    let Rgb([r, g, b]) = *output.get_pixel(px, py);
+    assert!(r > 200 && g < 60 && b < 60, "expected the red logo, got {:?}", (r, g, b));
+    let original = report.image_path.with_file_name(format!(
+        "{}_original.jpg",
+        report.image_path.file_stem().unwrap().to_string_lossy()
+    ));
+    let Rgb([r, _, _]) = *image::open(original).unwrap().to_rgb8().get_pixel(px, py);
+    assert!(r < 200);
+
+    // A second run finds the day completed.
+    let again = run_once(&config, &data_dir, &FakeSource { image: dir.join("missing.jpg") }).unwrap();
+    assert!(again.skipped);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+#[cfg_attr(windows, ignore = "sets the real desktop wallpaper")]
+fn lost_status_does_not_stamp_twice() {
+    let dir = scratch("lost-status");
+    let (photo, logo) = fixtures(&dir);
+    let data_dir = dir.join("data");
+    let mut config = config_with_logo(&logo);
+    // Half opacity, so a second stamp would visibly change the pixels.
+    if let Watermark::Image { opacity, .. } = &mut config.watermarks[0] {
+        *opacity = 50;
+    }
+    let source = FakeSource { image: photo };
+
+    let report = run_once(&config, &data_dir, &source).unwrap();
+    let stamped = fs::read(&report.image_path).unwrap();
+
+    // Lose the status and the clean copy, leaving only the watermarked image.
+    let dfolder = report.image_path.parent().unwrap();
+    fs::remove_file(dfolder.join("status.json")).unwrap();
+    let stem = report.image_path.file_stem().unwrap().to_string_lossy().into_owned();
+    fs::remove_file(dfolder.join(format!("{stem}_original.jpg"))).unwrap();
+
+    let rerun = run_once(&config, &data_dir, &source).unwrap();
+    assert!(!rerun.skipped);
+    assert!(rerun.watermark_added);
+    assert_eq!(fs::read(&rerun.image_path).unwrap(), stamped, "image was watermarked a second time");
+    assert_eq!(read_status(&rerun.image_path)["watermark_added"], true);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn in_place_matches_separate_output() {
+    let dir = scratch("in-place");
+    let (photo, logo) = fixtures(&dir);
+    let mut config = config_with_logo(&logo);
+    if let Watermark::Image { opacity, .. } = &mut config.watermarks[0] {
+        *opacity = 50;
+    }
+    let mut logger = Logger::new(&dir.join("log.txt"));
+
+    let separate = dir.join("separate.jpg");
+    add_watermarks(&photo, &separate, &config, &dir, &mut logger);
+    let in_place = dir.join("in_place.jpg");
+    fs::copy(&photo, &in_place).unwrap();
+    add_watermarks(&in_place, &in_place, &config, &dir, &mut logger);
+
+    let separate = fs::read(&separate).unwrap();
+    assert_ne!(separate, fs::read(&photo).unwrap(), "no watermark was applied");
+    assert_eq!(fs::read(&in_place).unwrap(), separate);
+
+    let _ = fs::remove_dir_all(&dir);
+}